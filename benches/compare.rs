@@ -0,0 +1,103 @@
+//! Equality, ordering and hashing of `Str` in map-heavy workloads
+//!
+//! Each benchmark has a `via_str` baseline over the same `Str` values that goes through `Deref` to `&str`,
+//! which is what every comparison and hash did before `Str` had fast paths of its own.
+//! The `sort` group compares `Str` with `PrefixStr`, which settles most comparisons on its inline prefix.
+
+#[macro_use]
+extern crate criterion;
+extern crate strref;
+
+use criterion::{black_box, Criterion};
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashMap};
+use std::hash::{Hash, Hasher};
+use strref::{IntoStr, PrefixStr, Str};
+
+// a `Str` key that compares and hashes through `&str`, bypassing the fast paths
+#[derive(Clone)]
+struct ViaStr(Str);
+
+impl PartialEq for ViaStr {
+    fn eq(&self, other: &ViaStr) -> bool {
+        *self.0 == *other.0
+    }
+}
+
+impl Eq for ViaStr {}
+
+impl PartialOrd for ViaStr {
+    fn partial_cmp(&self, other: &ViaStr) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for ViaStr {
+    fn cmp(&self, other: &ViaStr) -> Ordering {
+        (*self.0).cmp(&*other.0)
+    }
+}
+
+impl Hash for ViaStr {
+    fn hash<H: Hasher>(&self, h: &mut H) {
+        (*self.0).hash(h)
+    }
+}
+
+fn keys() -> Vec<Str> {
+    (0..1000).map(|i| format!("key-{}", i).into_str()).collect()
+}
+
+fn long_keys() -> Vec<Str> {
+    (0..1000).map(|i| format!("a longer key that is stored on the heap #{}", i).into_str()).collect()
+}
+
+fn eq(c: &mut Criterion) {
+    let small = keys();
+    let long = long_keys();
+    let long_clones = long.clone();
+    let mut group = c.benchmark_group("eq");
+    group.bench_function("small", |b| b.iter(|| small.iter().zip(small.iter().rev()).filter(|&(x, y)| x == y).count()));
+    group.bench_function("small/via_str", |b| b.iter(|| small.iter().zip(small.iter().rev()).filter(|&(x, y)| **x == **y).count()));
+    group.bench_function("shared", |b| b.iter(|| long.iter().zip(&long_clones).filter(|&(x, y)| x == y).count()));
+    group.bench_function("shared/via_str", |b| b.iter(|| long.iter().zip(&long_clones).filter(|&(x, y)| **x == **y).count()));
+    group.finish();
+}
+
+fn maps(c: &mut Criterion) {
+    let small = keys();
+    let hash: HashMap<Str, usize> = small.iter().cloned().zip(0..).collect();
+    let tree: BTreeMap<Str, usize> = small.iter().cloned().zip(0..).collect();
+    let via_str: Vec<ViaStr> = small.iter().cloned().map(ViaStr).collect();
+    let hash_via_str: HashMap<ViaStr, usize> = via_str.iter().cloned().zip(0..).collect();
+    let tree_via_str: BTreeMap<ViaStr, usize> = via_str.iter().cloned().zip(0..).collect();
+    let mut group = c.benchmark_group("maps");
+    group.bench_function("hash_map", |b| b.iter(|| small.iter().filter_map(|k| hash.get(black_box(k))).count()));
+    group.bench_function("hash_map/via_str", |b| b.iter(|| via_str.iter().filter_map(|k| hash_via_str.get(black_box(k))).count()));
+    group.bench_function("btree_map", |b| b.iter(|| small.iter().filter_map(|k| tree.get(black_box(k))).count()));
+    group.bench_function("btree_map/via_str", |b| b.iter(|| via_str.iter().filter_map(|k| tree_via_str.get(black_box(k))).count()));
+    group.finish();
+}
+
+// heap-backed keys in shuffled order that mostly differ in their first 4 bytes, like ids or names
+fn shuffled_keys() -> Vec<Str> {
+    (0..1000).map(|i| format!("{:04} is a key that is stored on the heap", i * 7919 % 10000).into_str()).collect()
+}
+
+fn sort(c: &mut Criterion) {
+    let long = shuffled_keys();
+    let prefixed: Vec<PrefixStr> = long.iter().cloned().map(PrefixStr::from).collect();
+    let mut sorted = long.clone();
+    sorted.sort();
+    let mut sorted_prefixed = prefixed.clone();
+    sorted_prefixed.sort();
+    let mut group = c.benchmark_group("sort");
+    group.bench_function("str", |b| b.iter(|| long.clone().sort()));
+    group.bench_function("prefix_str", |b| b.iter(|| prefixed.clone().sort()));
+    group.bench_function("binary_search/str", |b| b.iter(|| long.iter().filter(|k| sorted.binary_search(black_box(k)).is_ok()).count()));
+    group.bench_function("binary_search/prefix_str", |b| b.iter(|| prefixed.iter().filter(|k| sorted_prefixed.binary_search(black_box(k)).is_ok()).count()));
+    group.finish();
+}
+
+criterion_group!(benches, eq, maps, sort);
+criterion_main!(benches);
@@ -0,0 +1,379 @@
+//! Incremental construction of `Str` values
+//!
+//! A `StrBuilder` writes into inline storage the size of a `SmallStr` and only moves to the heap
+//! once the string outgrows it.
+//! The heap buffer already has the layout of an `ArcStr`, so `finish()` hands it over without copying.
+
+use core::borrow::Borrow;
+use core::fmt::{self, Debug, Display};
+use core::str;
+#[cfg(feature = "std")]
+use std::io;
+
+use super::{Str, SmallStr, StrRef};
+use heap::ArcStrBuf;
+
+enum Buf {
+    Inline(SmallStr),
+    Heap(ArcStrBuf),
+}
+
+/// A growable string that finishes into the cheapest `Str` representation
+///
+/// ```
+/// use std::fmt::Write;
+/// use strref::StrBuilder;
+///
+/// let mut b = StrBuilder::new();
+/// write!(b, "{}-{}", "built", 42).unwrap();
+/// b.push('!');
+/// assert_eq!("built-42!", b.finish());
+/// ```
+pub struct StrBuilder {
+    buf: Buf,
+    // the start of a UTF-8 sequence split across `io::Write::write()` calls
+    #[cfg_attr(not(feature = "std"), allow(dead_code))]
+    partial: [u8; 4],
+    partial_len: u8,
+}
+
+impl StrBuilder {
+    pub fn new() -> StrBuilder {
+        StrBuilder {
+            buf: Buf::Inline(SmallStr::new()),
+            partial: [0; 4],
+            partial_len: 0,
+        }
+    }
+
+    /// Creates a builder that can hold `capacity` bytes without reallocating
+    pub fn with_capacity(capacity: usize) -> StrBuilder {
+        let mut b = StrBuilder::new();
+        b.reserve(capacity);
+        b
+    }
+
+    pub fn len(&self) -> usize {
+        self.as_str().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn capacity(&self) -> usize {
+        match self.buf {
+            Buf::Inline(_) => <SmallStr>::CAPACITY,
+            Buf::Heap(ref heap) => heap.capacity(),
+        }
+    }
+
+    pub fn as_str(&self) -> &str {
+        match self.buf {
+            Buf::Inline(ref t) => t.borrow(),
+            Buf::Heap(ref heap) => heap.as_str(),
+        }
+    }
+
+    /// Makes room for at least `additional` more bytes
+    pub fn reserve(&mut self, additional: usize) {
+        let needed = self.len() + additional;
+        if needed <= self.capacity() {
+            return;
+        }
+        match self.buf {
+            Buf::Heap(ref mut heap) => heap.reserve(additional),
+            Buf::Inline(t) => {
+                let mut heap = ArcStrBuf::with_capacity(needed);
+                heap.push_str(t.borrow());
+                self.buf = Buf::Heap(heap);
+            }
+        }
+    }
+
+    pub fn push(&mut self, c: char) {
+        self.push_str(c.encode_utf8(&mut [0; 4]))
+    }
+
+    /// Appends `s`
+    ///
+    /// An incomplete UTF-8 sequence left by `io::Write` is replaced with U+FFFD first, so the bytes stay in order.
+    pub fn push_str(&mut self, s: &str) {
+        if self.partial_len > 0 {
+            self.partial_len = 0;
+            self.append(char::REPLACEMENT_CHARACTER.encode_utf8(&mut [0; 4]));
+        }
+        self.append(s);
+    }
+
+    fn append(&mut self, s: &str) {
+        if let Buf::Inline(ref mut t) = self.buf {
+            if t.try_push_str(s).is_ok() {
+                return;
+            }
+        }
+        self.reserve(s.len());
+        if let Buf::Heap(ref mut heap) = self.buf {
+            heap.push_str(s);
+        }
+    }
+
+    /// Finishes the string, storing it inline if it fits
+    ///
+    /// An incomplete UTF-8 sequence left at the end by `io::Write` is replaced with U+FFFD.
+    pub fn finish(mut self) -> Str {
+        self.push_str("");
+        match self.buf {
+            Buf::Inline(t) => Str::Small(t),
+            Buf::Heap(heap) => {
+                if heap.len() <= <SmallStr>::CAPACITY {
+                    return Str::Small(SmallStr::copy_from(heap.as_str()));
+                }
+                Str::Rc(heap.into_arc_str())
+            }
+        }
+    }
+
+    // completes a UTF-8 sequence split across writes, returning how many bytes of `buf` it took
+    //
+    // A sequence that can't be completed is dropped and reported as an error. Nothing reaches the string
+    // until the sequence is complete, so none of `buf` has been written then, and the next call starts afresh.
+    #[cfg(feature = "std")]
+    fn complete_partial(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut taken = 0;
+        while self.partial_len > 0 && taken < buf.len() {
+            self.partial[self.partial_len as usize] = buf[taken];
+            self.partial_len += 1;
+            taken += 1;
+            let partial = self.partial;
+            match str::from_utf8(&partial[..self.partial_len as usize]) {
+                Ok(s) => {
+                    self.partial_len = 0;
+                    self.append(s);
+                }
+                Err(e) if e.error_len().is_some() => {
+                    self.partial_len = 0;
+                    return Err(io::Error::new(io::ErrorKind::InvalidData, e));
+                }
+                Err(_) => (),
+            }
+        }
+        Ok(taken)
+    }
+}
+
+impl Default for StrBuilder {
+    fn default() -> StrBuilder {
+        StrBuilder::new()
+    }
+}
+
+impl Debug for StrBuilder {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        Debug::fmt(self.as_str(), f)
+    }
+}
+
+impl fmt::Write for StrBuilder {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.push_str(s);
+        Ok(())
+    }
+
+    fn write_char(&mut self, c: char) -> fmt::Result {
+        self.push(c);
+        Ok(())
+    }
+}
+
+#[cfg(feature = "std")]
+impl io::Write for StrBuilder {
+    /// Appends `buf`, which must be UTF-8, though a character may be split across calls
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let taken = self.complete_partial(buf)?;
+        let rest = &buf[taken..];
+        match str::from_utf8(rest) {
+            Ok(s) => self.append(s),
+            Err(e) => {
+                let valid = e.valid_up_to();
+                self.append(unsafe { str::from_utf8_unchecked(&rest[..valid]) });
+                if e.error_len().is_some() {
+                    // report the bytes taken so far; the next call starts at the bad sequence and fails
+                    if taken + valid > 0 {
+                        return Ok(taken + valid);
+                    }
+                    return Err(io::Error::new(io::ErrorKind::InvalidData, e));
+                }
+                let tail = &rest[valid..];
+                self.partial[..tail.len()].copy_from_slice(tail);
+                self.partial_len = tail.len() as u8;
+            }
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Formats any `Display` value straight into a `Str`
+pub trait DisplayStr : Display {
+    /// Like `to_string()`, but stays inline when the result fits in a `SmallStr`
+    fn to_display_str(&self) -> Str {
+        Str::from_fmt(format_args!("{}", self))
+    }
+}
+
+impl<T: Display + ?Sized> DisplayStr for T {}
+
+impl Str {
+    /// Formats `args` into a `Str` without going through a `String`
+    ///
+    /// Arguments that are only a literal become `Str::Static`. Anything else is written inline
+    /// and moves to the heap only if it outgrows a `SmallStr`, ending in a single exact-size allocation.
+    pub fn from_fmt(args: fmt::Arguments) -> Str {
+        if let Some(s) = args.as_str() {
+            return Str::Static(s);
+        }
+        let mut b = StrBuilder::new();
+        fmt::Write::write_fmt(&mut b, args).expect("a Display implementation returned an error");
+        b.finish()
+    }
+
+    /// Concatenates `pieces` into a single `Str`, sizing the buffer exactly up front
+    pub fn concat<I, S>(pieces: I) -> Str
+        where I: IntoIterator<Item = S>, I::IntoIter: Clone, S: StrRef
+    {
+        Str::join(pieces, "")
+    }
+
+    /// Joins `pieces` with `sep` between them, sizing the buffer exactly up front
+    pub fn join<I, S>(pieces: I, sep: &str) -> Str
+        where I: IntoIterator<Item = S>, I::IntoIter: Clone, S: StrRef
+    {
+        let pieces = pieces.into_iter();
+        let (count, len) = pieces.clone()
+            .fold((0usize, 0), |(count, len), s| (count + 1, len + s.borrow_str().len()));
+        let mut b = StrBuilder::with_capacity(len + sep.len() * count.saturating_sub(1));
+        for (i, s) in pieces.enumerate() {
+            if i > 0 {
+                b.push_str(sep);
+            }
+            b.push_str(s.borrow_str());
+        }
+        b.finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{StrBuilder, DisplayStr};
+    use super::super::{Str, IntoStr};
+    use std::fmt::Write;
+
+    #[test]
+    fn stays_inline() {
+        let mut b = StrBuilder::new();
+        let n = 1;
+        write!(b, "short {}", n).unwrap();
+        match b.finish() {
+            Str::Small(t) => assert_eq!("short 1", t.to_string()),
+            other => panic!("expected a small string, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn spills_to_heap() {
+        let mut b = StrBuilder::new();
+        for i in 0..10 {
+            write!(b, "{},", i * 1000).unwrap();
+        }
+        assert!(b.capacity() > 23);
+        match b.finish() {
+            Str::Rc(ref rc) => assert_eq!("0,1000,2000,3000,4000,5000,6000,7000,8000,9000,", &**rc),
+            other => panic!("expected a heap string, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn with_capacity() {
+        let mut b = StrBuilder::with_capacity(40);
+        assert_eq!(40, b.capacity());
+        b.push_str("a string of exactly forty bytes in total");
+        let p = b.as_str().as_ptr();
+        assert_eq!(40, b.capacity());
+        assert_eq!(p, b.finish().as_ptr());
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn io_write() {
+        use std::io::Write;
+
+        let mut b = StrBuilder::new();
+        let bytes = "ünïcödé written through io::Write".as_bytes();
+        // split inside the two-byte 'ü'
+        b.write_all(&bytes[..1]).unwrap();
+        b.write_all(&bytes[1..]).unwrap();
+        assert_eq!("ünïcödé written through io::Write", b.finish());
+
+        let mut b = StrBuilder::new();
+        assert!(b.write_all(b"bad \xff byte").is_err());
+        assert_eq!("bad ", b.as_str());
+
+        let mut b = StrBuilder::new();
+        b.write_all(&bytes[..1]).unwrap();
+        assert_eq!("\u{FFFD}", b.finish());
+        // a sequence broken by the next write is dropped, and writing carries on after the error
+        let mut b = StrBuilder::new();
+        assert_eq!(1, b.write(b"\xf0").unwrap());
+        assert!(b.write(b"A").is_err());
+        for _ in 0..4 {
+            assert_eq!(1, b.write(b"A").unwrap());
+        }
+        b.write_all(&bytes[..1]).unwrap();
+        b.write_all(&bytes[1..2]).unwrap();
+        assert_eq!("AAAAü", b.finish());
+        // pushing text in the middle of a sequence ends it, rather than putting the text before it
+        let mut b = StrBuilder::new();
+        b.write_all(b"a\xc3").unwrap();
+        b.push_str("x");
+        b.write_all(b"\xbc").unwrap_err();
+        b.push('y');
+        assert_eq!("a\u{FFFD}xy", b.finish());
+    }
+
+    #[test]
+    fn join() {
+        let parts = ["a", "list", "of", "words", "joined", "together"];
+        let joined = Str::join(parts.iter().cloned(), ", ");
+        assert_eq!("a, list, of, words, joined, together", joined);
+        match joined {
+            Str::Rc(ref rc) => assert_eq!(36, rc.len()),
+            ref other => panic!("expected a heap string, got {:?}", other),
+        }
+        let strs = vec!["con".into_str(), "cat".into_str()];
+        assert_eq!("concat", Str::concat(&strs));
+        assert_eq!("", Str::join(Vec::<Str>::new(), ", "));
+    }
+
+    #[test]
+    fn format_str() {
+        match format_str!("a literal without arguments that is long") {
+            Str::Static(s) => assert_eq!("a literal without arguments that is long", s),
+            other => panic!("expected a static string, got {:?}", other),
+        }
+        let n = 7;
+        match format_str!("{} items", n) {
+            Str::Small(t) => assert_eq!("7 items", t.to_string()),
+            other => panic!("expected a small string, got {:?}", other),
+        }
+        let long = format_str!("{} items, formatted into something longer", n);
+        match long {
+            Str::Rc(ref rc) => assert_eq!("7 items, formatted into something longer", &**rc),
+            ref other => panic!("expected a heap string, got {:?}", other),
+        }
+        assert_eq!("3.5", 3.5.to_display_str());
+    }
+}
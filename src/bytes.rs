@@ -0,0 +1,447 @@
+//! Immutable shared byte strings
+//!
+//! `ByteStr` is the binary counterpart of `Str`: up to 23 bytes are stored inline,
+//! longer runtime values share a single reference-counted allocation and literals are kept as `&'static [u8]`.
+//! The heap allocation is the same one `Str` uses, so converting a `Str` into a `ByteStr` never copies.
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::borrow::Borrow;
+use core::cmp::Ordering;
+use core::convert::TryFrom;
+use core::fmt::{self, Debug};
+use core::hash::{Hash, Hasher};
+use core::ops::{Deref, RangeBounds, Bound};
+use core::str;
+
+use super::{Str, SmallStr, SubStr, ArcStr, ArcBytes, StrError};
+use super::small::Len;
+
+/// Up to 23 bytes stored inline
+#[derive(Clone, Copy)]
+pub struct SmallBytes {
+    // an enum like `SmallStr`'s length, so that `ByteStr` also keeps its discriminant in the spare values
+    len: Len,
+    bytes: [u8; 23],
+}
+
+impl SmallBytes {
+    pub(crate) const CAPACITY: usize = 23;
+
+    // the caller checks that `source` fits
+    fn copy_from(source: &[u8]) -> SmallBytes {
+        let mut small = SmallBytes {
+            len: Len::new(source.len()),
+            bytes: [0; 23],
+        };
+        small.bytes[..source.len()].copy_from_slice(source);
+        small
+    }
+
+    /// Copies `source` into a `SmallBytes`, failing if it is longer than 23 bytes
+    pub fn try_from_slice(source: &[u8]) -> Result<SmallBytes, StrError> {
+        if source.len() > SmallBytes::CAPACITY {
+            return Err(StrError::TooLong { len: source.len(), capacity: SmallBytes::CAPACITY });
+        }
+        Ok(SmallBytes::copy_from(source))
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes[..self.len.get()]
+    }
+}
+
+impl Deref for SmallBytes {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        self.as_bytes()
+    }
+}
+
+impl Debug for SmallBytes {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "b\"{}\"", self.as_bytes().escape_ascii())
+    }
+}
+
+/// A range of reference-counted bytes that shares its parent's allocation
+#[derive(Clone)]
+pub struct SubBytes {
+    rc: ArcBytes,
+    start: u32,
+    len: u32,
+}
+
+impl SubBytes {
+    fn new(rc: &ArcBytes, start: usize, end: usize) -> Option<SubBytes> {
+        if end > u32::MAX as usize {
+            return None;
+        }
+        Some(SubBytes {
+            rc: rc.clone(),
+            start: start as u32,
+            len: (end - start) as u32,
+        })
+    }
+}
+
+impl Deref for SubBytes {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        let start = self.start as usize;
+        &self.rc[start..start + self.len as usize]
+    }
+}
+
+impl Debug for SubBytes {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "b\"{}\"", self.escape_ascii())
+    }
+}
+
+#[derive(Clone)]
+pub enum ByteStr {
+    Small(SmallBytes),
+    Rc(ArcBytes),
+    Static(&'static [u8]),
+    Sub(SubBytes),
+}
+
+impl ByteStr {
+    /// Copies `bytes`, inline if they fit or else into a single exact-size allocation
+    pub fn copy_from(bytes: &[u8]) -> ByteStr {
+        if bytes.len() <= SmallBytes::CAPACITY {
+            return ByteStr::Small(SmallBytes::copy_from(bytes));
+        }
+        ByteStr::Rc(ArcBytes::new(bytes))
+    }
+
+    /// Returns the bytes in `range` without copying them, as `Str::slice()` does
+    ///
+    /// # Panics
+    ///
+    /// Panics if either end of `range` is out of bounds
+    pub fn slice<R: RangeBounds<usize>>(&self, range: R) -> ByteStr {
+        let start = match range.start_bound() {
+            Bound::Included(&i) => i,
+            Bound::Excluded(&i) => i + 1,
+            Bound::Unbounded => 0,
+        };
+        let end = match range.end_bound() {
+            Bound::Included(&i) => i + 1,
+            Bound::Excluded(&i) => i,
+            Bound::Unbounded => self.len(),
+        };
+        let sub: &[u8] = &self.borrow_bytes()[start..end];
+        if start == 0 && end == self.len() {
+            return self.clone();
+        }
+        if sub.len() <= SmallBytes::CAPACITY {
+            return ByteStr::Small(SmallBytes::copy_from(sub));
+        }
+        let shared = match *self {
+            ByteStr::Static(b) => Some(ByteStr::Static(&b[start..end])),
+            ByteStr::Rc(ref rc) => SubBytes::new(rc, start, end).map(ByteStr::Sub),
+            ByteStr::Sub(ref parent) => {
+                let offset = parent.start as usize;
+                SubBytes::new(&parent.rc, offset + start, offset + end).map(ByteStr::Sub)
+            }
+            ByteStr::Small(_) => None,
+        };
+        shared.unwrap_or_else(|| ByteStr::Rc(ArcBytes::new(sub)))
+    }
+
+    fn borrow_bytes(&self) -> &[u8] {
+        match *self {
+            ByteStr::Small(ref t) => t,
+            ByteStr::Rc(ref rc) => rc,
+            ByteStr::Static(b) => b,
+            ByteStr::Sub(ref sub) => sub,
+        }
+    }
+}
+
+pub trait ByteStrRef {
+    fn borrow_bytes(&self) -> &[u8];
+}
+
+pub trait IntoByteStr : ByteStrRef {
+    fn into_byte_str(self) -> ByteStr;
+}
+
+impl ByteStrRef for ByteStr {
+    fn borrow_bytes(&self) -> &[u8] {
+        self.borrow_bytes()
+    }
+}
+
+impl<T: ByteStrRef + ?Sized> ByteStrRef for &T {
+    fn borrow_bytes(&self) -> &[u8] {
+        (**self).borrow_bytes()
+    }
+}
+
+impl ByteStrRef for [u8] {
+    fn borrow_bytes(&self) -> &[u8] {
+        self
+    }
+}
+
+impl<const N: usize> ByteStrRef for [u8; N] {
+    fn borrow_bytes(&self) -> &[u8] {
+        self
+    }
+}
+
+impl ByteStrRef for Vec<u8> {
+    fn borrow_bytes(&self) -> &[u8] {
+        self
+    }
+}
+
+impl ByteStrRef for ArcBytes {
+    fn borrow_bytes(&self) -> &[u8] {
+        self
+    }
+}
+
+impl ByteStrRef for str {
+    fn borrow_bytes(&self) -> &[u8] {
+        self.as_bytes()
+    }
+}
+
+impl ByteStrRef for String {
+    fn borrow_bytes(&self) -> &[u8] {
+        self.as_bytes()
+    }
+}
+
+impl ByteStrRef for Str {
+    fn borrow_bytes(&self) -> &[u8] {
+        self.as_bytes()
+    }
+}
+
+impl IntoByteStr for ByteStr {
+    fn into_byte_str(self) -> ByteStr {
+        self
+    }
+}
+
+impl IntoByteStr for &'static [u8] {
+    fn into_byte_str(self) -> ByteStr {
+        ByteStr::Static(self)
+    }
+}
+
+impl<const N: usize> IntoByteStr for &'static [u8; N] {
+    fn into_byte_str(self) -> ByteStr {
+        ByteStr::Static(self)
+    }
+}
+
+impl IntoByteStr for &'static str {
+    fn into_byte_str(self) -> ByteStr {
+        ByteStr::Static(self.as_bytes())
+    }
+}
+
+impl IntoByteStr for Vec<u8> {
+    fn into_byte_str(self) -> ByteStr {
+        ByteStr::copy_from(&self)
+    }
+}
+
+impl IntoByteStr for String {
+    fn into_byte_str(self) -> ByteStr {
+        ByteStr::copy_from(self.as_bytes())
+    }
+}
+
+impl IntoByteStr for ArcBytes {
+    fn into_byte_str(self) -> ByteStr {
+        ByteStr::Rc(self)
+    }
+}
+
+impl IntoByteStr for Str {
+    /// Shares the bytes of the string; only an `Str::ArcString` has to be copied
+    fn into_byte_str(self) -> ByteStr {
+        match self {
+            Str::Small(t) => ByteStr::Small(SmallBytes::copy_from(t.as_bytes())),
+            Str::Rc(rc) => ByteStr::Rc(rc.into_bytes()),
+            Str::Static(s) => ByteStr::Static(s.as_bytes()),
+            Str::Sub(sub) => ByteStr::Sub(SubBytes { rc: sub.rc, start: sub.start, len: sub.len }),
+            Str::ArcString(rc) => ByteStr::copy_from(rc.as_bytes()),
+        }
+    }
+}
+
+impl From<Str> for ByteStr {
+    fn from(s: Str) -> ByteStr {
+        s.into_byte_str()
+    }
+}
+
+impl TryFrom<ByteStr> for Str {
+    type Error = StrError;
+
+    /// Checks that the bytes are UTF-8 and shares them with the returned `Str`
+    fn try_from(b: ByteStr) -> Result<Str, StrError> {
+        Ok(match b {
+            ByteStr::Small(t) => Str::Small(SmallStr::copy_from(str::from_utf8(&t)?)),
+            ByteStr::Rc(rc) => Str::Rc(ArcStr::from_utf8(rc)?),
+            ByteStr::Static(b) => Str::Static(str::from_utf8(b)?),
+            ByteStr::Sub(sub) => {
+                str::from_utf8(&sub)?;
+                Str::Sub(SubStr { rc: sub.rc, start: sub.start, len: sub.len })
+            }
+        })
+    }
+}
+
+impl Deref for ByteStr {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        self.borrow_bytes()
+    }
+}
+
+impl AsRef<[u8]> for ByteStr {
+    fn as_ref(&self) -> &[u8] {
+        self.borrow_bytes()
+    }
+}
+
+impl Borrow<[u8]> for ByteStr {
+    fn borrow(&self) -> &[u8] {
+        self.borrow_bytes()
+    }
+}
+
+impl Debug for ByteStr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "b\"{}\"", self.borrow_bytes().escape_ascii())
+    }
+}
+
+impl PartialEq for ByteStr {
+    fn eq(&self, other: &ByteStr) -> bool {
+        self.borrow_bytes() == other.borrow_bytes()
+    }
+}
+
+impl Eq for ByteStr {}
+
+impl PartialEq<[u8]> for ByteStr {
+    fn eq(&self, other: &[u8]) -> bool {
+        self.borrow_bytes() == other
+    }
+}
+
+impl PartialEq<ByteStr> for [u8] {
+    fn eq(&self, other: &ByteStr) -> bool {
+        self == other.borrow_bytes()
+    }
+}
+
+impl PartialEq<ByteStr> for &'static [u8] {
+    fn eq(&self, other: &ByteStr) -> bool {
+        *self == other.borrow_bytes()
+    }
+}
+
+impl PartialOrd for ByteStr {
+    fn partial_cmp(&self, other: &ByteStr) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for ByteStr {
+    fn cmp(&self, other: &ByteStr) -> Ordering {
+        self.borrow_bytes().cmp(other.borrow_bytes())
+    }
+}
+
+impl Hash for ByteStr {
+    fn hash<H: Hasher>(&self, h: &mut H) {
+        self.borrow_bytes().hash(h)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ByteStr, IntoByteStr};
+    use super::super::{Str, IntoStr, StrError};
+    use std::collections::HashMap;
+    use std::convert::TryFrom;
+
+    const LONG: &str = "String value that is too long to fit in small string";
+
+    #[test]
+    fn size() {
+        assert_eq!(24, ::std::mem::size_of::<ByteStr>());
+    }
+
+    #[test]
+    fn representation() {
+        match b"short".into_byte_str() {
+            ByteStr::Static(b) => assert_eq!(b"short", b),
+            other => panic!("expected static bytes, got {:?}", other),
+        }
+        match b"short".to_vec().into_byte_str() {
+            ByteStr::Small(t) => assert_eq!(b"short", &*t),
+            other => panic!("expected small bytes, got {:?}", other),
+        }
+        match LONG.as_bytes().to_vec().into_byte_str() {
+            ByteStr::Rc(ref rc) => assert_eq!(LONG.as_bytes(), &**rc),
+            other => panic!("expected heap bytes, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn from_str_without_copying() {
+        let s = LONG.to_string().into_str();
+        let b = ByteStr::from(s.clone());
+        assert_eq!(s.as_ptr(), b.as_ptr());
+        let sub = s.slice(7..);
+        assert_eq!(sub.as_ptr(), ByteStr::from(sub.clone()).as_ptr());
+
+        let back = Str::try_from(b).unwrap();
+        assert_eq!(s.as_ptr(), back.as_ptr());
+        assert_eq!(s, back);
+    }
+
+    #[test]
+    fn into_str_checks_utf8() {
+        let b = b"\xffString value that is too long to fit".to_vec().into_byte_str();
+        match Str::try_from(b.clone()) {
+            Err(StrError::InvalidUtf8(_)) => (),
+            other => panic!("expected invalid UTF-8, got {:?}", other),
+        }
+        // a valid range of an invalid buffer still converts without copying
+        let tail = b.slice(1..);
+        let s = Str::try_from(tail.clone()).unwrap();
+        assert_eq!(tail.as_ptr(), s.as_ptr());
+        assert_eq!("String value that is too long to fit", s);
+    }
+
+    #[test]
+    fn map_key() {
+        let mut map = HashMap::new();
+        map.insert(LONG.as_bytes().to_vec().into_byte_str(), 1);
+        map.insert(b"key".into_byte_str(), 2);
+        assert_eq!(Some(&1), map.get(LONG.as_bytes()));
+        assert_eq!(Some(&2), map.get(&b"key"[..]));
+        assert!(b"a".into_byte_str() < b"b".to_vec().into_byte_str());
+    }
+
+    #[test]
+    fn debug() {
+        assert_eq!("b\"bin\\xffary\"", format!("{:?}", b"bin\xffary".to_vec().into_byte_str()));
+    }
+}
@@ -0,0 +1,364 @@
+//! Case-insensitive string keys
+//!
+//! `CaseInsensitive` wraps a string and compares, orders and hashes it by its case-folded form,
+//! folding one character at a time so nothing is allocated.
+//! The original spelling is kept for `Display`.
+//! Maps keyed by it can be queried with a plain `&str` through `CaseInsensitiveStr`.
+
+use core::borrow::Borrow;
+use core::cmp::Ordering;
+use core::fmt::{self, Debug, Display};
+use core::hash::{Hash, Hasher};
+use core::marker::PhantomData;
+use core::ops::Deref;
+
+use super::{Str, StrRef};
+use case_folding;
+
+/// How `CaseInsensitive` folds case
+pub trait CaseFolding {
+    fn eq(a: &str, b: &str) -> bool;
+    fn cmp(a: &str, b: &str) -> Ordering;
+    fn hash<H: Hasher>(s: &str, h: &mut H);
+}
+
+/// Folds only ASCII letters, leaving every other character as it is
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct AsciiCase;
+
+impl CaseFolding for AsciiCase {
+    fn eq(a: &str, b: &str) -> bool {
+        a.eq_ignore_ascii_case(b)
+    }
+
+    fn cmp(a: &str, b: &str) -> Ordering {
+        let lower = |b: u8| b.to_ascii_lowercase();
+        a.bytes().map(lower).cmp(b.bytes().map(lower))
+    }
+
+    fn hash<H: Hasher>(s: &str, h: &mut H) {
+        for b in s.bytes() {
+            h.write_u8(b.to_ascii_lowercase());
+        }
+        h.write_u8(0xff);
+    }
+}
+
+/// Applies Unicode full case folding, as defined by CaseFolding.txt
+///
+/// "Straße", "STRAẞE" and "STRASSE" are all equal, and 'ſ' and the Kelvin sign fold to 's' and 'k'.
+/// The Turkic mappings are not applied, so the dotless 'ı' stays distinct from 'i'.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct UnicodeCase;
+
+impl UnicodeCase {
+    fn fold(s: &str) -> impl Iterator<Item = char> + '_ {
+        s.chars().flat_map(|c| {
+            // on ASCII this is `to_ascii_lowercase()`, which the fast path in `eq` relies on
+            let (single, full) = match case_folding::fold(c) {
+                Some(full) => (None, full),
+                None => (Some(c.to_ascii_lowercase()), ""),
+            };
+            single.into_iter().chain(full.chars())
+        })
+    }
+}
+
+impl CaseFolding for UnicodeCase {
+    fn eq(a: &str, b: &str) -> bool {
+        if a.is_ascii() && b.is_ascii() {
+            return a.eq_ignore_ascii_case(b);
+        }
+        UnicodeCase::fold(a).eq(UnicodeCase::fold(b))
+    }
+
+    fn cmp(a: &str, b: &str) -> Ordering {
+        UnicodeCase::fold(a).cmp(UnicodeCase::fold(b))
+    }
+
+    fn hash<H: Hasher>(s: &str, h: &mut H) {
+        for c in UnicodeCase::fold(s) {
+            h.write_u32(c as u32);
+        }
+        h.write_u8(0xff);
+    }
+}
+
+/// A string that compares, orders and hashes without regard to case
+///
+/// ```
+/// use std::collections::HashMap;
+/// use strref::{CaseInsensitive, CaseInsensitiveStr, IntoStr};
+///
+/// let mut headers = HashMap::new();
+/// headers.insert(CaseInsensitive::new("Content-Type".into_str()), "text/plain");
+/// assert_eq!(Some(&"text/plain"), headers.get(CaseInsensitiveStr::new("content-type")));
+/// ```
+pub struct CaseInsensitive<S = Str, M = AsciiCase> {
+    s: S,
+    mode: PhantomData<M>,
+}
+
+impl<S: StrRef> CaseInsensitive<S, AsciiCase> {
+    /// Wraps `s` to compare with ASCII case folding
+    pub fn new(s: S) -> CaseInsensitive<S, AsciiCase> {
+        CaseInsensitive::with_folding(s)
+    }
+}
+
+impl<S: StrRef> CaseInsensitive<S, UnicodeCase> {
+    /// Wraps `s` to compare with Unicode case folding
+    pub fn unicode(s: S) -> CaseInsensitive<S, UnicodeCase> {
+        CaseInsensitive::with_folding(s)
+    }
+}
+
+impl<S: StrRef, M: CaseFolding> CaseInsensitive<S, M> {
+    pub fn with_folding(s: S) -> CaseInsensitive<S, M> {
+        CaseInsensitive { s, mode: PhantomData }
+    }
+
+    pub fn as_str(&self) -> &str {
+        self.s.borrow_str()
+    }
+
+    pub fn get_ref(&self) -> &S {
+        &self.s
+    }
+
+    pub fn into_inner(self) -> S {
+        self.s
+    }
+}
+
+impl<S: StrRef, M: CaseFolding> From<S> for CaseInsensitive<S, M> {
+    fn from(s: S) -> CaseInsensitive<S, M> {
+        CaseInsensitive::with_folding(s)
+    }
+}
+
+impl<S: Clone, M> Clone for CaseInsensitive<S, M> {
+    fn clone(&self) -> CaseInsensitive<S, M> {
+        CaseInsensitive { s: self.s.clone(), mode: PhantomData }
+    }
+}
+
+impl<S, M> Deref for CaseInsensitive<S, M> {
+    type Target = S;
+
+    fn deref(&self) -> &S {
+        &self.s
+    }
+}
+
+impl<S: StrRef, M: CaseFolding> Borrow<CaseInsensitiveStr<M>> for CaseInsensitive<S, M> {
+    fn borrow(&self) -> &CaseInsensitiveStr<M> {
+        CaseInsensitiveStr::with_folding(self.s.borrow_str())
+    }
+}
+
+impl<S: StrRef, M: CaseFolding> StrRef for CaseInsensitive<S, M> {
+    fn borrow_str(&self) -> &str {
+        self.s.borrow_str()
+    }
+}
+
+impl<S: Display, M> Display for CaseInsensitive<S, M> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        Display::fmt(&self.s, f)
+    }
+}
+
+impl<S: Debug, M> Debug for CaseInsensitive<S, M> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        Debug::fmt(&self.s, f)
+    }
+}
+
+impl<S: StrRef, M: CaseFolding> PartialEq for CaseInsensitive<S, M> {
+    fn eq(&self, other: &CaseInsensitive<S, M>) -> bool {
+        M::eq(self.as_str(), other.as_str())
+    }
+}
+
+impl<S: StrRef, M: CaseFolding> Eq for CaseInsensitive<S, M> {}
+
+impl<S: StrRef, M: CaseFolding> PartialEq<str> for CaseInsensitive<S, M> {
+    fn eq(&self, other: &str) -> bool {
+        M::eq(self.as_str(), other)
+    }
+}
+
+impl<'a, S: StrRef, M: CaseFolding> PartialEq<&'a str> for CaseInsensitive<S, M> {
+    fn eq(&self, other: &&'a str) -> bool {
+        M::eq(self.as_str(), other)
+    }
+}
+
+impl<S: StrRef, M: CaseFolding> PartialOrd for CaseInsensitive<S, M> {
+    fn partial_cmp(&self, other: &CaseInsensitive<S, M>) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<S: StrRef, M: CaseFolding> Ord for CaseInsensitive<S, M> {
+    fn cmp(&self, other: &CaseInsensitive<S, M>) -> Ordering {
+        M::cmp(self.as_str(), other.as_str())
+    }
+}
+
+impl<S: StrRef, M: CaseFolding> Hash for CaseInsensitive<S, M> {
+    fn hash<H: Hasher>(&self, h: &mut H) {
+        M::hash(self.as_str(), h)
+    }
+}
+
+/// The borrowed form of `CaseInsensitive`, for looking up keys from a plain `&str`
+#[repr(transparent)]
+pub struct CaseInsensitiveStr<M = AsciiCase> {
+    mode: PhantomData<M>,
+    s: str,
+}
+
+impl CaseInsensitiveStr<AsciiCase> {
+    pub fn new(s: &str) -> &CaseInsensitiveStr<AsciiCase> {
+        CaseInsensitiveStr::with_folding(s)
+    }
+}
+
+impl CaseInsensitiveStr<UnicodeCase> {
+    pub fn unicode(s: &str) -> &CaseInsensitiveStr<UnicodeCase> {
+        CaseInsensitiveStr::with_folding(s)
+    }
+}
+
+impl<M: CaseFolding> CaseInsensitiveStr<M> {
+    pub fn with_folding(s: &str) -> &CaseInsensitiveStr<M> {
+        // sound because of `repr(transparent)`: the only non-zero-sized field is the `str`
+        unsafe { &*(s as *const str as *const CaseInsensitiveStr<M>) }
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.s
+    }
+}
+
+impl<M> Display for CaseInsensitiveStr<M> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        Display::fmt(&self.s, f)
+    }
+}
+
+impl<M> Debug for CaseInsensitiveStr<M> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        Debug::fmt(&self.s, f)
+    }
+}
+
+impl<M: CaseFolding> PartialEq for CaseInsensitiveStr<M> {
+    fn eq(&self, other: &CaseInsensitiveStr<M>) -> bool {
+        M::eq(&self.s, &other.s)
+    }
+}
+
+impl<M: CaseFolding> Eq for CaseInsensitiveStr<M> {}
+
+impl<M: CaseFolding> PartialOrd for CaseInsensitiveStr<M> {
+    fn partial_cmp(&self, other: &CaseInsensitiveStr<M>) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<M: CaseFolding> Ord for CaseInsensitiveStr<M> {
+    fn cmp(&self, other: &CaseInsensitiveStr<M>) -> Ordering {
+        M::cmp(&self.s, &other.s)
+    }
+}
+
+impl<M: CaseFolding> Hash for CaseInsensitiveStr<M> {
+    fn hash<H: Hasher>(&self, h: &mut H) {
+        M::hash(&self.s, h)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CaseInsensitive, CaseInsensitiveStr, UnicodeCase};
+    use super::super::{Str, IntoStr};
+    use std::cmp::Ordering;
+    use std::collections::{BTreeMap, HashMap};
+    use std::collections::hash_map::RandomState;
+    use std::hash::BuildHasher;
+
+    #[test]
+    fn ascii() {
+        let a: CaseInsensitive = CaseInsensitive::new("Content-Type".into_str());
+        let b: CaseInsensitive = CaseInsensitive::new("CONTENT-TYPE".into_str());
+        assert_eq!(a, b);
+        assert_eq!(a, "content-type");
+        let state = RandomState::new();
+        assert_eq!(state.hash_one(&a), state.hash_one(&b));
+        assert_eq!(state.hash_one(&a), state.hash_one(CaseInsensitiveStr::new("content-TYPE")));
+        assert_eq!("Content-Type", a.to_string());
+        // only ASCII letters fold
+        let c: CaseInsensitive<&str> = CaseInsensitive::new("STRASSE");
+        assert_ne!(c, "straße");
+        assert_ne!(CaseInsensitive::new("É"), "é");
+    }
+
+    #[test]
+    fn unicode() {
+        let a: CaseInsensitive<Str, UnicodeCase> = CaseInsensitive::unicode("Straße".into_str());
+        assert_eq!(a, "STRASSE");
+        assert_eq!(a, "strasse");
+        let e = CaseInsensitive::unicode("Élan");
+        assert_eq!(e, "éLAN");
+        let state = RandomState::new();
+        assert_eq!(state.hash_one(&a), state.hash_one(CaseInsensitiveStr::unicode("STRASSE")));
+
+        // capital sharp s folds to "ss" like the small one, and dotless i has no folding outside Turkic
+        let capital = CaseInsensitive::unicode("STRAẞE");
+        assert_eq!(capital, "straße");
+        assert_eq!(capital, "strasse");
+        assert_eq!(state.hash_one(&capital), state.hash_one(&a));
+        assert_eq!(Ordering::Equal, capital.cmp(&CaseInsensitive::unicode("Strasse")));
+        assert_ne!(CaseInsensitive::unicode("ı"), "i");
+        assert_ne!(CaseInsensitive::unicode("ı"), "I");
+        assert_eq!(CaseInsensitive::unicode("İ"), "i\u{307}");
+        assert_eq!(CaseInsensitive::unicode("\u{212A}elvin"), "KELVIN");
+    }
+
+    #[test]
+    fn unicode_ascii_agrees() {
+        // the ASCII fast path in `eq` has to agree with the folding `cmp` and `hash` use
+        let state = RandomState::new();
+        let words = ["", "a", "A", "ab", "AB", "Ab", "b", "[", "_", "`", "{"];
+        for &x in words.iter() {
+            for &y in words.iter() {
+                let (a, b) = (CaseInsensitive::unicode(x), CaseInsensitive::unicode(y));
+                assert_eq!(a == b, a.cmp(&b) == Ordering::Equal, "{:?} {:?}", x, y);
+                if a == b {
+                    assert_eq!(state.hash_one(&a), state.hash_one(&b));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn map_lookup() {
+        let mut map = HashMap::new();
+        map.insert(CaseInsensitive::new("Accept-Encoding".into_str()), 1);
+        map.insert(CaseInsensitive::new("Content-Length".into_str()), 2);
+        assert_eq!(Some(&1), map.get(CaseInsensitiveStr::new("ACCEPT-ENCODING")));
+        assert_eq!(Some(&2), map.get(CaseInsensitiveStr::new("content-length")));
+        assert_eq!(None, map.get(CaseInsensitiveStr::new("content-type")));
+
+        let mut tree = BTreeMap::new();
+        for &key in ["select", "FROM", "Where"].iter() {
+            tree.insert(CaseInsensitive::new(key.into_str()), ());
+        }
+        let order: Vec<String> = tree.keys().map(|k| k.to_string()).collect();
+        assert_eq!(vec!["FROM", "select", "Where"], order);
+        assert!(tree.contains_key(CaseInsensitiveStr::new("WHERE")));
+    }
+}
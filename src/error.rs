@@ -0,0 +1,48 @@
+//! Errors from fallible string conversions
+
+use core::error::Error;
+use core::fmt::{self, Display};
+use core::str::Utf8Error;
+
+/// The reason a conversion into `Str` or `SmallStr` failed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StrError {
+    /// the string doesn't fit in the inline storage of a `SmallStr`
+    TooLong {
+        len: usize,
+        capacity: usize,
+    },
+    /// the string is shared, so it can't be taken by value without copying
+    Shared,
+    /// the string isn't held in a `String`, so there is none to take back
+    NotString,
+    /// the bytes aren't valid UTF-8
+    InvalidUtf8(Utf8Error),
+}
+
+impl Display for StrError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            StrError::TooLong { len, capacity } =>
+                write!(f, "string of {} bytes doesn't fit in {} bytes of inline storage", len, capacity),
+            StrError::Shared => f.write_str("string is shared and can't be taken without copying"),
+            StrError::NotString => f.write_str("string isn't held in a String"),
+            StrError::InvalidUtf8(ref e) => Display::fmt(e, f),
+        }
+    }
+}
+
+impl Error for StrError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match *self {
+            StrError::InvalidUtf8(ref e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<Utf8Error> for StrError {
+    fn from(e: Utf8Error) -> StrError {
+        StrError::InvalidUtf8(e)
+    }
+}
@@ -0,0 +1,257 @@
+//! Strings with a precomputed hash
+//!
+//! `HashedStr` hashes its string once, with process-wide random keys, and feeds only that 64-bit value
+//! to a `Hasher`. Maps built with `BuildPrecomputedHasher` use it as is instead of rehashing the bytes.
+//! `HashedStrKey` is the borrowed form, so a plain `&str` can be looked up without building a `HashedStr`.
+
+use std::borrow::Borrow;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::collections::hash_map::RandomState;
+use std::fmt::{self, Debug, Display};
+use std::hash::{BuildHasher, BuildHasherDefault, Hash, Hasher};
+use std::ops::Deref;
+use std::sync::OnceLock;
+
+use super::{Str, StrRef, IntoStr};
+
+fn hash_str(s: &str) -> u64 {
+    static STATE: OnceLock<RandomState> = OnceLock::new();
+    STATE.get_or_init(RandomState::new).hash_one(s)
+}
+
+/// A `Str` that carries its own hash, computed once when the `HashedStr` is built
+#[derive(Clone)]
+pub struct HashedStr {
+    s: Str,
+    hash: u64,
+}
+
+impl HashedStr {
+    pub fn new<S: IntoStr>(s: S) -> HashedStr {
+        let s = s.into_str();
+        let hash = hash_str(&s);
+        HashedStr { s, hash }
+    }
+
+    pub fn hash_value(&self) -> u64 {
+        self.hash
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.s
+    }
+
+    pub fn get_ref(&self) -> &Str {
+        &self.s
+    }
+
+    pub fn into_inner(self) -> Str {
+        self.s
+    }
+}
+
+impl<S: IntoStr> From<S> for HashedStr {
+    fn from(s: S) -> HashedStr {
+        HashedStr::new(s)
+    }
+}
+
+impl Deref for HashedStr {
+    type Target = Str;
+
+    fn deref(&self) -> &Str {
+        &self.s
+    }
+}
+
+impl StrRef for HashedStr {
+    fn borrow_str(&self) -> &str {
+        &self.s
+    }
+}
+
+impl Borrow<HashedStrKey> for HashedStr {
+    fn borrow(&self) -> &HashedStrKey {
+        HashedStrKey::new(&self.s)
+    }
+}
+
+impl Display for HashedStr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        Display::fmt(&self.s, f)
+    }
+}
+
+impl Debug for HashedStr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        Debug::fmt(&self.s, f)
+    }
+}
+
+impl PartialEq for HashedStr {
+    /// Rejects strings whose hashes differ without comparing their bytes
+    fn eq(&self, other: &HashedStr) -> bool {
+        self.hash == other.hash && self.s == other.s
+    }
+}
+
+impl Eq for HashedStr {}
+
+impl PartialEq<str> for HashedStr {
+    fn eq(&self, other: &str) -> bool {
+        &*self.s == other
+    }
+}
+
+impl<'a> PartialEq<&'a str> for HashedStr {
+    fn eq(&self, other: &&'a str) -> bool {
+        &*self.s == *other
+    }
+}
+
+impl PartialOrd for HashedStr {
+    fn partial_cmp(&self, other: &HashedStr) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for HashedStr {
+    fn cmp(&self, other: &HashedStr) -> Ordering {
+        self.s.cmp(&other.s)
+    }
+}
+
+impl Hash for HashedStr {
+    fn hash<H: Hasher>(&self, h: &mut H) {
+        h.write_u64(self.hash)
+    }
+}
+
+/// The borrowed form of `HashedStr`, for looking up keys from a plain `&str`
+///
+/// It hashes the string the same way `HashedStr` does, so it finds keys in maps with any hasher.
+#[repr(transparent)]
+pub struct HashedStrKey {
+    s: str,
+}
+
+impl HashedStrKey {
+    pub fn new(s: &str) -> &HashedStrKey {
+        // sound because of `repr(transparent)`
+        unsafe { &*(s as *const str as *const HashedStrKey) }
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.s
+    }
+}
+
+impl Debug for HashedStrKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        Debug::fmt(&self.s, f)
+    }
+}
+
+impl PartialEq for HashedStrKey {
+    fn eq(&self, other: &HashedStrKey) -> bool {
+        self.s == other.s
+    }
+}
+
+impl Eq for HashedStrKey {}
+
+impl Hash for HashedStrKey {
+    fn hash<H: Hasher>(&self, h: &mut H) {
+        h.write_u64(hash_str(&self.s))
+    }
+}
+
+/// A `Hasher` that passes the precomputed hash of a `HashedStr` straight through
+///
+/// Only a key that is a `HashedStr` alone is passed through: a hash written after anything else is mixed in,
+/// and other data is mixed in with FNV-1a, so composite keys such as `(u32, HashedStr)` still hash all their parts.
+/// A plain `u64` key hashes to itself, which spreads poorly in a `HashMap`; use another hasher for those.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PrecomputedHasher {
+    // `None` until something is written
+    hash: Option<u64>,
+}
+
+impl Hasher for PrecomputedHasher {
+    fn write(&mut self, bytes: &[u8]) {
+        let mut hash = self.hash.unwrap_or(0xcbf2_9ce4_8422_2325);
+        for &b in bytes {
+            hash = (hash ^ u64::from(b)).wrapping_mul(0x0100_0000_01b3);
+        }
+        self.hash = Some(hash);
+    }
+
+    fn write_u64(&mut self, hash: u64) {
+        self.hash = Some(match self.hash {
+            None => hash,
+            Some(prev) => (prev.rotate_left(5) ^ hash).wrapping_mul(0x517c_c1b7_2722_0a95),
+        });
+    }
+
+    fn finish(&self) -> u64 {
+        self.hash.unwrap_or(0xcbf2_9ce4_8422_2325)
+    }
+}
+
+/// Builds `PrecomputedHasher`s for maps keyed by `HashedStr`
+pub type BuildPrecomputedHasher = BuildHasherDefault<PrecomputedHasher>;
+
+/// A `HashMap` keyed by `HashedStr` that never rehashes its keys
+pub type HashedStrMap<V> = HashMap<HashedStr, V, BuildPrecomputedHasher>;
+
+#[cfg(test)]
+mod tests {
+    use super::{HashedStr, HashedStrKey, HashedStrMap, BuildPrecomputedHasher};
+    use std::hash::BuildHasher;
+    use super::super::IntoStr;
+    use std::collections::HashMap;
+
+    const LONG: &str = "a key long enough to be stored on the heap";
+
+    #[test]
+    fn hash_value() {
+        let key = HashedStr::new("static key");
+        assert_eq!(key.hash_value(), HashedStr::new(String::from("static key")).hash_value());
+        assert_eq!(key.hash_value(), key.clone().hash_value());
+        assert_ne!(key.hash_value(), HashedStr::new(LONG).hash_value());
+    }
+
+    #[test]
+    fn eq() {
+        let a = HashedStr::new(LONG.to_string());
+        assert_eq!(a, a.clone());
+        assert_eq!(a, HashedStr::new(LONG));
+        assert_ne!(a, HashedStr::new(LONG.to_uppercase()));
+        assert_eq!(a, LONG);
+    }
+
+    #[test]
+    fn map_lookup() {
+        let mut map = HashedStrMap::default();
+        map.insert(HashedStr::new(LONG.to_string().into_str()), 1);
+        map.insert(HashedStr::new("short"), 2);
+        assert_eq!(Some(&1), map.get(&HashedStr::new(LONG)));
+        assert_eq!(Some(&1), map.get(HashedStrKey::new(LONG)));
+        assert_eq!(Some(&2), map.get(HashedStrKey::new("short")));
+        assert_eq!(None, map.get(HashedStrKey::new("missing")));
+
+        // keys with more than a `HashedStr` in them hash every part
+        let hasher = BuildPrecomputedHasher::default();
+        let key = HashedStr::new(LONG);
+        assert_eq!(key.hash_value(), hasher.hash_one(&key));
+        assert_ne!(hasher.hash_one((1u32, &key)), hasher.hash_one((2u32, &key)));
+        assert_ne!(hasher.hash_one((&key, 1u64)), hasher.hash_one((&key, 2u64)));
+        assert_ne!(hasher.hash_one((1u64, &key)), hasher.hash_one((2u64, &key)));
+
+        // the hash is also consistent under a regular hasher
+        let mut std_map = HashMap::new();
+        std_map.insert(HashedStr::new(LONG), 1);
+        assert_eq!(Some(&1), std_map.get(HashedStrKey::new(LONG)));
+    }
+}
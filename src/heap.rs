@@ -0,0 +1,528 @@
+//! Thin reference-counted string storage
+//!
+//! An `ArcBytes` keeps its reference counts, its length and its bytes in a single heap allocation
+//! and is only one pointer wide, so reaching the bytes of a heap-backed `Str` takes one indirection.
+//! `ArcStr` is the same allocation holding valid UTF-8, so text and bytes can share it.
+
+use alloc::alloc::{self, Layout};
+use alloc::string::String;
+use core::borrow::Borrow;
+use core::fmt::{Display, Debug};
+use core::mem;
+use core::ops::Deref;
+use core::ptr::{self, NonNull};
+use core::slice;
+use core::str;
+use core::sync::atomic::{self, AtomicUsize, Ordering};
+
+use super::StrError;
+#[cfg(feature = "std")]
+use intern;
+
+#[repr(C)]
+struct Header {
+    strong: AtomicUsize,
+    // all strong references together hold one weak reference, as with `std::sync::Arc`
+    weak: AtomicUsize,
+    // the length of the bytes, with the `INTERNED` bit set if the global interner lists the allocation
+    len: usize,
+}
+
+impl Header {
+    fn len(&self) -> usize {
+        self.len & !INTERNED
+    }
+
+    #[cfg(feature = "std")]
+    fn is_interned(&self) -> bool {
+        self.len & INTERNED != 0
+    }
+}
+
+// the bytes start right after the header
+const DATA_OFFSET: usize = mem::size_of::<Header>();
+
+// allocations are limited to `isize::MAX` bytes, so no length has the top bit set
+const INTERNED: usize = !(usize::MAX >> 1);
+
+// same limit as `std::sync::Arc`: abort well before a count could overflow
+const MAX_REFCOUNT: usize = isize::MAX as usize;
+
+#[cfg(feature = "std")]
+fn abort() -> ! {
+    std::process::abort()
+}
+
+// `core` has no way to abort, but a panic while already panicking always does
+#[cfg(not(feature = "std"))]
+fn abort() -> ! {
+    struct PanicOnDrop;
+
+    impl Drop for PanicOnDrop {
+        fn drop(&mut self) {
+            panic!("reference count overflow");
+        }
+    }
+
+    let _guard = PanicOnDrop;
+    panic!("reference count overflow");
+}
+
+fn layout(len: usize) -> Layout {
+    Layout::array::<u8>(len)
+        .and_then(|bytes| Layout::new::<Header>().extend(bytes))
+        .map(|(layout, _)| layout.pad_to_align())
+        .expect("capacity overflow")
+}
+
+/// Immutable, atomically reference-counted bytes stored in a single allocation
+pub struct ArcBytes {
+    ptr: NonNull<Header>,
+}
+
+unsafe impl Send for ArcBytes {}
+unsafe impl Sync for ArcBytes {}
+
+impl ArcBytes {
+    /// Copies `bytes` into a new allocation of exactly the right size
+    pub fn new(bytes: &[u8]) -> ArcBytes {
+        ArcBytes::with_flags(bytes, 0)
+    }
+
+    fn with_flags(bytes: &[u8], flags: usize) -> ArcBytes {
+        let layout = layout(bytes.len());
+        unsafe {
+            let raw = alloc::alloc(layout) as *mut Header;
+            let ptr = NonNull::new(raw).unwrap_or_else(|| alloc::handle_alloc_error(layout));
+            ptr::write(raw, Header {
+                strong: AtomicUsize::new(1),
+                weak: AtomicUsize::new(1),
+                len: bytes.len() | flags,
+            });
+            ptr::copy_nonoverlapping(bytes.as_ptr(), (raw as *mut u8).add(DATA_OFFSET), bytes.len());
+            ArcBytes { ptr }
+        }
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        unsafe {
+            let data = (self.ptr.as_ptr() as *const u8).add(DATA_OFFSET);
+            slice::from_raw_parts(data, self.header().len())
+        }
+    }
+
+    /// Returns true if both values point to the same allocation
+    pub fn ptr_eq(this: &ArcBytes, other: &ArcBytes) -> bool {
+        this.ptr == other.ptr
+    }
+
+    /// Returns the number of strong references to this allocation
+    pub fn strong_count(this: &ArcBytes) -> usize {
+        this.header().strong.load(Ordering::Acquire)
+    }
+
+    /// Returns the size in bytes of the allocation, counts and length included
+    pub fn allocation_size(this: &ArcBytes) -> usize {
+        layout(this.header().len()).size()
+    }
+
+    pub(crate) fn downgrade(this: &ArcBytes) -> WeakArcStr {
+        this.header().weak.fetch_add(1, Ordering::Relaxed);
+        WeakArcStr { ptr: this.ptr }
+    }
+
+    fn header(&self) -> &Header {
+        unsafe { self.ptr.as_ref() }
+    }
+}
+
+impl Clone for ArcBytes {
+    fn clone(&self) -> ArcBytes {
+        if self.header().strong.fetch_add(1, Ordering::Relaxed) > MAX_REFCOUNT {
+            abort();
+        }
+        ArcBytes { ptr: self.ptr }
+    }
+}
+
+impl Drop for ArcBytes {
+    fn drop(&mut self) {
+        if self.header().strong.fetch_sub(1, Ordering::Release) != 1 {
+            return;
+        }
+        atomic::fence(Ordering::Acquire);
+        // the bytes need no destructor; release the weak reference held by the strong ones
+        let weak = WeakArcStr { ptr: self.ptr };
+        // the interner's entry would otherwise keep the allocation around; no string can be revived
+        // from a count of zero, so nothing else is going to take it out
+        #[cfg(feature = "std")]
+        {
+            if weak.header().is_interned() {
+                intern::remove(&weak);
+            }
+        }
+        drop(weak);
+    }
+}
+
+impl Deref for ArcBytes {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        self.as_bytes()
+    }
+}
+
+impl Borrow<[u8]> for ArcBytes {
+    fn borrow(&self) -> &[u8] {
+        self.as_bytes()
+    }
+}
+
+impl<'a> From<&'a [u8]> for ArcBytes {
+    fn from(bytes: &'a [u8]) -> ArcBytes {
+        ArcBytes::new(bytes)
+    }
+}
+
+impl From<ArcStr> for ArcBytes {
+    fn from(s: ArcStr) -> ArcBytes {
+        s.bytes
+    }
+}
+
+impl Debug for ArcBytes {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::result::Result<(), core::fmt::Error> {
+        write!(f, "b\"{}\"", self.as_bytes().escape_ascii())
+    }
+}
+
+/// An immutable, atomically reference-counted string stored in a single allocation
+#[derive(Clone)]
+pub struct ArcStr {
+    // always valid UTF-8
+    bytes: ArcBytes,
+}
+
+impl ArcStr {
+    /// Copies `s` into a new allocation of exactly the right size
+    pub fn new(s: &str) -> ArcStr {
+        ArcStr { bytes: ArcBytes::new(s.as_bytes()) }
+    }
+
+    // like `new()`, for a string the interner lists and must be told about when it is dropped
+    #[cfg(feature = "std")]
+    pub(crate) fn new_interned(s: &str) -> ArcStr {
+        ArcStr { bytes: ArcBytes::with_flags(s.as_bytes(), INTERNED) }
+    }
+
+    /// Shares the allocation of `bytes` if it holds valid UTF-8
+    pub fn from_utf8(bytes: ArcBytes) -> Result<ArcStr, StrError> {
+        str::from_utf8(&bytes)?;
+        Ok(ArcStr { bytes })
+    }
+
+    pub fn as_str(&self) -> &str {
+        unsafe { str::from_utf8_unchecked(self.bytes.as_bytes()) }
+    }
+
+    /// Returns the underlying bytes, sharing the allocation
+    pub fn into_bytes(self) -> ArcBytes {
+        self.bytes
+    }
+
+    pub(crate) fn as_arc_bytes(&self) -> &ArcBytes {
+        &self.bytes
+    }
+
+    /// Returns true if both values point to the same allocation
+    pub fn ptr_eq(this: &ArcStr, other: &ArcStr) -> bool {
+        ArcBytes::ptr_eq(&this.bytes, &other.bytes)
+    }
+
+    /// Returns the number of strong references to this allocation
+    pub fn strong_count(this: &ArcStr) -> usize {
+        ArcBytes::strong_count(&this.bytes)
+    }
+
+    /// Returns the size in bytes of the allocation, counts and length included
+    pub fn allocation_size(this: &ArcStr) -> usize {
+        ArcBytes::allocation_size(&this.bytes)
+    }
+
+    pub(crate) fn downgrade(this: &ArcStr) -> WeakArcStr {
+        ArcBytes::downgrade(&this.bytes)
+    }
+}
+
+impl Deref for ArcStr {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl Borrow<str> for ArcStr {
+    fn borrow(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl<'a> From<&'a str> for ArcStr {
+    fn from(s: &'a str) -> ArcStr {
+        ArcStr::new(s)
+    }
+}
+
+impl From<String> for ArcStr {
+    fn from(s: String) -> ArcStr {
+        ArcStr::new(&s)
+    }
+}
+
+impl Debug for ArcStr {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::result::Result<(), core::fmt::Error> {
+        Debug::fmt(self.as_str(), f)
+    }
+}
+
+impl Display for ArcStr {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::result::Result<(), core::fmt::Error> {
+        Display::fmt(self.as_str(), f)
+    }
+}
+
+/// A growable, uniquely owned buffer with the layout of an `ArcStr`,
+/// so that a finished string is handed over without copying
+pub(crate) struct ArcStrBuf {
+    // `len` in the header is the number of bytes written so far
+    ptr: NonNull<Header>,
+    cap: usize,
+}
+
+unsafe impl Send for ArcStrBuf {}
+unsafe impl Sync for ArcStrBuf {}
+
+impl ArcStrBuf {
+    pub fn with_capacity(cap: usize) -> ArcStrBuf {
+        let layout = layout(cap);
+        unsafe {
+            let raw = alloc::alloc(layout) as *mut Header;
+            let ptr = NonNull::new(raw).unwrap_or_else(|| alloc::handle_alloc_error(layout));
+            ptr::write(raw, Header {
+                strong: AtomicUsize::new(1),
+                weak: AtomicUsize::new(1),
+                len: 0,
+            });
+            ArcStrBuf { ptr, cap }
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        unsafe { self.ptr.as_ref().len }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.cap
+    }
+
+    pub fn as_str(&self) -> &str {
+        unsafe {
+            let data = (self.ptr.as_ptr() as *const u8).add(DATA_OFFSET);
+            str::from_utf8_unchecked(slice::from_raw_parts(data, self.len()))
+        }
+    }
+
+    pub fn push_str(&mut self, s: &str) {
+        let len = self.len();
+        self.reserve(s.len());
+        unsafe {
+            let data = (self.ptr.as_ptr() as *mut u8).add(DATA_OFFSET);
+            ptr::copy_nonoverlapping(s.as_ptr(), data.add(len), s.len());
+            self.ptr.as_mut().len = len + s.len();
+        }
+    }
+
+    pub fn reserve(&mut self, additional: usize) {
+        let needed = self.len().checked_add(additional).expect("capacity overflow");
+        if needed > self.cap {
+            self.resize(needed.max(self.cap.saturating_mul(2)));
+        }
+    }
+
+    /// Gives up the buffer as an `ArcStr`, releasing any spare capacity
+    pub fn into_arc_str(mut self) -> ArcStr {
+        let len = self.len();
+        if len != self.cap {
+            self.resize(len);
+        }
+        let ptr = self.ptr;
+        mem::forget(self);
+        ArcStr { bytes: ArcBytes { ptr } }
+    }
+
+    fn resize(&mut self, cap: usize) {
+        let old = layout(self.cap);
+        let new = layout(cap);
+        unsafe {
+            let raw = alloc::realloc(self.ptr.as_ptr() as *mut u8, old, new.size()) as *mut Header;
+            self.ptr = NonNull::new(raw).unwrap_or_else(|| alloc::handle_alloc_error(new));
+        }
+        self.cap = cap;
+    }
+}
+
+impl Drop for ArcStrBuf {
+    fn drop(&mut self) {
+        unsafe { alloc::dealloc(self.ptr.as_ptr() as *mut u8, layout(self.cap)) }
+    }
+}
+
+/// A non-owning reference to the allocation of an `ArcStr` or `ArcBytes`
+pub(crate) struct WeakArcStr {
+    ptr: NonNull<Header>,
+}
+
+unsafe impl Send for WeakArcStr {}
+unsafe impl Sync for WeakArcStr {}
+
+impl WeakArcStr {
+    pub fn upgrade(&self) -> Option<ArcStr> {
+        let strong = &self.header().strong;
+        let mut n = strong.load(Ordering::Relaxed);
+        loop {
+            if n == 0 {
+                return None;
+            }
+            if n > MAX_REFCOUNT {
+                abort();
+            }
+            match strong.compare_exchange_weak(n, n + 1, Ordering::Acquire, Ordering::Relaxed) {
+                Ok(_) => return Some(ArcStr { bytes: ArcBytes { ptr: self.ptr } }),
+                Err(old) => n = old,
+            }
+        }
+    }
+
+    pub fn strong_count(&self) -> usize {
+        self.header().strong.load(Ordering::Acquire)
+    }
+
+    /// Returns true if both point to the same allocation, whether or not it is still alive
+    pub fn ptr_eq(this: &WeakArcStr, other: &WeakArcStr) -> bool {
+        this.ptr == other.ptr
+    }
+
+    // identifies the allocation; stays valid after the string is dropped, for as long as this reference lives
+    pub fn as_ptr(&self) -> *const u8 {
+        self.ptr.as_ptr() as *const u8
+    }
+
+    // the bytes are never dropped, so they can be read for as long as the allocation lives
+    #[cfg(feature = "std")]
+    pub fn as_bytes(&self) -> &[u8] {
+        unsafe {
+            let data = (self.ptr.as_ptr() as *const u8).add(DATA_OFFSET);
+            slice::from_raw_parts(data, self.header().len())
+        }
+    }
+
+    fn header(&self) -> &Header {
+        unsafe { self.ptr.as_ref() }
+    }
+}
+
+impl Clone for WeakArcStr {
+    fn clone(&self) -> WeakArcStr {
+        self.header().weak.fetch_add(1, Ordering::Relaxed);
+        WeakArcStr { ptr: self.ptr }
+    }
+}
+
+impl Drop for WeakArcStr {
+    fn drop(&mut self) {
+        if self.header().weak.fetch_sub(1, Ordering::Release) != 1 {
+            return;
+        }
+        atomic::fence(Ordering::Acquire);
+        let layout = layout(self.header().len());
+        unsafe { alloc::dealloc(self.ptr.as_ptr() as *mut u8, layout) }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ArcStr, ArcBytes, ArcStrBuf};
+    use std::thread;
+
+    #[test]
+    fn size() {
+        assert_eq!(::std::mem::size_of::<usize>(), ::std::mem::size_of::<ArcStr>());
+    }
+
+    #[test]
+    fn counts() {
+        let a = ArcStr::new("a reference counted string");
+        let b = a.clone();
+        assert!(ArcStr::ptr_eq(&a, &b));
+        assert_eq!(2, ArcStr::strong_count(&a));
+        drop(b);
+        assert_eq!(1, ArcStr::strong_count(&a));
+        assert_eq!("a reference counted string", &*a);
+    }
+
+    #[test]
+    fn bytes() {
+        let a = ArcStr::new("a reference counted string");
+        let b = a.clone().into_bytes();
+        assert_eq!(b"a reference counted string", &*b);
+        let back = ArcStr::from_utf8(b.clone()).unwrap();
+        assert!(ArcStr::ptr_eq(&a, &back));
+        assert!(ArcStr::from_utf8(ArcBytes::new(b"\xff")).is_err());
+        assert_eq!("b\"\\xff\"", format!("{:?}", ArcBytes::new(b"\xff")));
+    }
+
+    #[test]
+    fn empty() {
+        let a = ArcStr::new("");
+        assert_eq!("", &*a);
+    }
+
+    #[test]
+    fn weak() {
+        let a = ArcStr::new("a reference counted string");
+        let w = ArcStr::downgrade(&a);
+        let w2 = w.clone();
+        assert!(ArcStr::ptr_eq(&a, &w.upgrade().unwrap()));
+        drop(a);
+        assert!(w.upgrade().is_none());
+        assert_eq!(0, w2.strong_count());
+    }
+
+    #[test]
+    fn buf() {
+        let mut buf = ArcStrBuf::with_capacity(4);
+        buf.push_str("a string ");
+        buf.push_str("built in pieces");
+        assert!(buf.capacity() >= buf.len());
+        assert_eq!("a string built in pieces", buf.as_str());
+        let a = buf.into_arc_str();
+        assert_eq!("a string built in pieces", &*a);
+        drop(ArcStr::downgrade(&a));
+        assert_eq!(1, ArcStr::strong_count(&a));
+    }
+
+    #[test]
+    fn shared_between_threads() {
+        let a = ArcStr::new("a reference counted string");
+        let handles: Vec<_> = (0..8).map(|_| {
+            let a = a.clone();
+            thread::spawn(move || (0..1000).map(|_| a.clone()).filter(|b| b.len() == a.len()).count())
+        }).collect();
+        for h in handles {
+            assert_eq!(1000, h.join().unwrap());
+        }
+        assert_eq!(1, ArcStr::strong_count(&a));
+    }
+}
@@ -0,0 +1,250 @@
+//! Global string interner
+//!
+//! `Str::intern()` looks runtime strings up in a process-wide table so that equal strings
+//! share a single heap allocation.
+//! The table only holds weak references, so it never keeps a string alive by itself;
+//! dropping the last reference to an interned string takes its entry out of the table.
+
+use alloc::vec::Vec;
+use std::collections::HashMap;
+use std::collections::hash_map::RandomState;
+use std::hash::BuildHasher;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, MutexGuard, OnceLock};
+
+use super::{Str, SmallStr};
+use heap::{ArcStr, WeakArcStr};
+
+const SHARD_COUNT: usize = 16;
+
+/// A snapshot of the global interner's counters
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct InternStats {
+    /// lookups that found an equal string already alive
+    pub hits: u64,
+    /// lookups that had to allocate a new string
+    pub misses: u64,
+    /// strings currently alive in the table
+    pub live: usize,
+}
+
+struct Shard {
+    // weak entries bucketed by the full hash of their string
+    buckets: HashMap<u64, Vec<WeakArcStr>>,
+}
+
+impl Shard {
+    fn new() -> Shard {
+        Shard { buckets: HashMap::new() }
+    }
+
+    // Only the match is upgraded: dropping a string inside the lock could be the last drop,
+    // which would try to take the same lock to remove its entry.
+    fn find(&self, hash: u64, s: &str) -> Option<ArcStr> {
+        self.buckets.get(&hash)?
+            .iter()
+            .filter(|weak| weak.as_bytes() == s.as_bytes())
+            .find_map(WeakArcStr::upgrade)
+    }
+
+    fn insert(&mut self, hash: u64, rc: &ArcStr) {
+        self.buckets.entry(hash).or_default().push(ArcStr::downgrade(rc));
+    }
+
+    fn remove(&mut self, hash: u64, dead: &WeakArcStr) {
+        if let Some(bucket) = self.buckets.get_mut(&hash) {
+            bucket.retain(|weak| !WeakArcStr::ptr_eq(weak, dead));
+            if bucket.is_empty() {
+                self.buckets.remove(&hash);
+            }
+        }
+    }
+
+    fn live(&self) -> usize {
+        self.buckets.values()
+            .map(|bucket| bucket.iter().filter(|weak| weak.strong_count() > 0).count())
+            .sum()
+    }
+}
+
+struct Interner {
+    hasher: RandomState,
+    shards: Vec<Mutex<Shard>>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl Interner {
+    fn global() -> &'static Interner {
+        static INTERNER: OnceLock<Interner> = OnceLock::new();
+        INTERNER.get_or_init(|| Interner {
+            hasher: RandomState::new(),
+            shards: (0..SHARD_COUNT).map(|_| Mutex::new(Shard::new())).collect(),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        })
+    }
+
+    fn hash(&self, bytes: &[u8]) -> u64 {
+        self.hasher.hash_one(bytes)
+    }
+
+    fn shard(&self, hash: u64) -> MutexGuard<'_, Shard> {
+        // the table is consistent between statements, so a poisoned lock is still usable
+        let shard = &self.shards[(hash >> 32) as usize % SHARD_COUNT];
+        shard.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn intern(&self, s: &str) -> ArcStr {
+        let hash = self.hash(s.as_bytes());
+
+        let mut shard = self.shard(hash);
+        if let Some(rc) = shard.find(hash, s) {
+            self.hits.fetch_add(1, Ordering::Relaxed);
+            return rc;
+        }
+        self.misses.fetch_add(1, Ordering::Relaxed);
+        let rc = ArcStr::new_interned(s);
+        shard.insert(hash, &rc);
+        rc
+    }
+
+    fn stats(&self) -> InternStats {
+        InternStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            live: (0..SHARD_COUNT).map(|i| self.shard((i as u64) << 32).live()).sum(),
+        }
+    }
+}
+
+pub fn intern(s: &str) -> Str {
+    if s.len() <= <SmallStr>::CAPACITY {
+        return Str::Small(SmallStr::copy_from(s));
+    }
+    Str::Rc(Interner::global().intern(s))
+}
+
+pub fn stats() -> InternStats {
+    Interner::global().stats()
+}
+
+// called by the last strong reference to an interned allocation as it is dropped
+pub(crate) fn remove(dead: &WeakArcStr) {
+    let interner = Interner::global();
+    let hash = interner.hash(dead.as_bytes());
+    interner.shard(hash).remove(hash, dead);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Interner;
+    use super::super::{Str, ArcStr};
+    use heap::WeakArcStr;
+    use std::thread;
+
+    fn arc(s: &Str) -> &ArcStr {
+        match *s {
+            Str::Rc(ref rc) => rc,
+            _ => panic!("expected a heap string, got {:?}", s),
+        }
+    }
+
+    #[test]
+    fn shares_allocation() {
+        let a = Str::intern(format!("{} shares an allocation", "interned"));
+        let b = Str::intern("interned shares an allocation");
+        assert!(ArcStr::ptr_eq(arc(&a), arc(&b)));
+        assert_eq!(a, b);
+    }
+
+    #[test]
+    fn small_strings_stay_inline() {
+        match Str::intern("tag") {
+            Str::Small(_) => (),
+            other => panic!("expected a small string, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn counts_hits_and_misses() {
+        let before = Str::intern_stats();
+        let a = Str::intern("a string counted by the interner statistics");
+        let b = Str::intern("a string counted by the interner statistics".to_string());
+        let after = Str::intern_stats();
+        assert!(after.misses > before.misses);
+        assert!(after.hits > before.hits);
+        drop((a, b));
+    }
+
+    #[test]
+    fn dropped_strings_are_not_kept_alive() {
+        let s = "a string that is dropped before it is interned again";
+        let a = Str::intern(s);
+        let weak = ArcStr::downgrade(arc(&a));
+        drop(a);
+        assert!(weak.upgrade().is_none());
+        let b = Str::intern(s);
+        assert_eq!(s, b);
+    }
+
+    #[test]
+    fn entries_go_with_their_strings() {
+        let s = "a string whose entry goes as soon as it is dropped";
+        let a = Str::intern(s);
+        let weak = ArcStr::downgrade(arc(&a));
+        let interner = Interner::global();
+        let hash = interner.hash(s.as_bytes());
+        let listed = || interner.shard(hash).buckets.get(&hash)
+            .is_some_and(|bucket| bucket.iter().any(|w| WeakArcStr::ptr_eq(w, &weak)));
+        assert!(listed());
+        let b = Str::intern(s);
+        drop(a);
+        assert!(listed());
+        drop(b);
+        assert!(!listed());
+        // only the test's own weak reference is left holding the allocation
+        assert_eq!(0, weak.strong_count());
+        assert!(weak.upgrade().is_none());
+    }
+
+    #[test]
+    fn only_interned_strings_are_listed() {
+        let s = "a string that is interned while an equal one is not";
+        let plain = Str::copy_from(s);
+        let interned = Str::intern(s);
+        assert!(!ArcStr::ptr_eq(arc(&plain), arc(&interned)));
+        drop(plain);
+        assert_eq!(s, Str::intern(s));
+        assert!(ArcStr::ptr_eq(arc(&interned), arc(&Str::intern(s))));
+    }
+
+    #[test]
+    fn concurrent() {
+        let handles: Vec<_> = (0..8).map(|_| thread::spawn(|| {
+            (0..100).map(|i| Str::intern(format!("concurrently interned string #{}", i))).collect::<Vec<_>>()
+        })).collect();
+        let results: Vec<Vec<Str>> = handles.into_iter().map(|h| h.join().unwrap()).collect();
+        for strs in &results[1..] {
+            for (a, b) in strs.iter().zip(&results[0]) {
+                assert!(ArcStr::ptr_eq(arc(a), arc(b)));
+            }
+        }
+    }
+
+    #[test]
+    fn concurrent_drops() {
+        // strings keep dying and being interned again, so removals race with lookups of the same entry
+        let handles: Vec<_> = (0..8).map(|_| thread::spawn(|| {
+            for i in 0..2000 {
+                let s = Str::intern(format!("a string interned and dropped over and over #{}", i % 10));
+                assert!(s.ends_with(&format!("#{}", i % 10)));
+            }
+        })).collect();
+        for h in handles {
+            h.join().unwrap();
+        }
+        let s = Str::intern("a string interned and dropped over and over #3");
+        assert!(ArcStr::ptr_eq(arc(&s), arc(&Str::intern("a string interned and dropped over and over #3"))));
+    }
+}
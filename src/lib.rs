@@ -58,108 +58,239 @@
 //! assert_eq!("built at runtime", my_struct.get_str(1).unwrap());
 //!
 //! ```
-
-
-use std::sync::Arc;
-use std::rc::Rc;
-use std::borrow::Borrow;
-use std::hash::{Hash, Hasher};
-use std::fmt::{Display, Debug};
-use std::ops::Deref;
-use std::cmp::{PartialOrd,Ordering};
-use std::str::from_utf8;
-
+//!
+//! # `no_std`
+//!
+//! Without the default `std` feature the crate only needs `core` and `alloc`.
+//! The string types and traits are all still there; what goes is anything built on the standard library:
+//! `OsStrRc` and `PathStr`, `HashedStr`, `Str::intern()`, the symbol tables and `io::Write` for `StrBuilder`.
+
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
+
+// `core` is only linked implicitly by `no_std` builds
+#[cfg(any(feature = "std", test))]
+extern crate core;
+extern crate alloc;
+#[cfg(feature = "deepsize")]
+extern crate deepsize;
+#[cfg(feature = "get-size")]
+extern crate get_size;
+#[cfg(feature = "serde")]
+extern crate serde;
+#[cfg(all(test, feature = "serde"))]
+extern crate serde_json;
+#[cfg(all(test, feature = "serde"))]
+extern crate serde_test;
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use alloc::sync::Arc;
+use alloc::rc::Rc;
+use core::borrow::Borrow;
+use core::hash::{Hash, Hasher};
+use core::fmt::{Display, Debug};
+use core::ops::{Deref, RangeBounds, Bound};
+use core::cmp::{PartialOrd,Ordering};
+use core::convert::TryFrom;
+use core::str::from_utf8;
+
+/// Builds a `Str` from a string literal, picking the representation at compile time
+///
+/// Literals of up to 23 bytes are stored inline and longer ones as `Str::Static`.
+/// The expansion is a constant, so it can initialize `const` and `static` items directly.
+///
+/// ```
+/// #[macro_use]
+/// extern crate strref;
+/// use strref::Str;
+///
+/// static KEYWORDS: [Str; 2] = [str!("fn"), str!("a keyword that is too long to be inlined")];
+///
+/// fn main() {
+///     assert!(matches!(KEYWORDS[0], Str::Small(_)));
+///     assert!(matches!(KEYWORDS[1], Str::Static(_)));
+/// }
+/// ```
+#[macro_export]
+macro_rules! str {
+    ($s:expr) => {{
+        const S: $crate::Str = $crate::Str::from_literal($s);
+        S
+    }};
+}
+
+/// Formats into a `Str` like `format!`, without an intermediate `String`
+///
+/// See `Str::from_fmt()` for the representation it picks.
+///
+/// ```
+/// #[macro_use]
+/// extern crate strref;
+///
+/// fn main() {
+///     let id = 42;
+///     assert_eq!("user-42", format_str!("user-{}", id));
+/// }
+/// ```
+#[macro_export]
+macro_rules! format_str {
+    ($($arg:tt)*) => {
+        $crate::Str::from_fmt(format_args!($($arg)*))
+    };
+}
+
+mod builder;
+mod bytes;
+mod case;
+mod case_folding;
+mod error;
+#[cfg(feature = "std")]
+mod hashed;
+mod heap;
+#[cfg(feature = "std")]
+mod intern;
+mod local;
+#[cfg(feature = "std")]
+mod path;
+mod prefix;
+mod rope;
+#[cfg(feature = "serde")]
+mod serde_impls;
+mod size;
+mod small;
+mod split;
+#[cfg(feature = "std")]
+mod symbol;
+mod weak;
+
+pub use builder::{StrBuilder, DisplayStr};
+pub use bytes::{ByteStr, SmallBytes, SubBytes, ByteStrRef, IntoByteStr};
+pub use case::{CaseInsensitive, CaseInsensitiveStr, CaseFolding, AsciiCase, UnicodeCase};
+pub use error::StrError;
+#[cfg(feature = "std")]
+pub use hashed::{HashedStr, HashedStrKey, HashedStrMap, PrecomputedHasher, BuildPrecomputedHasher};
+pub use heap::{ArcStr, ArcBytes};
+#[cfg(feature = "std")]
+pub use intern::InternStats;
+pub use local::LocalStr;
+#[cfg(feature = "std")]
+pub use path::{OsStrRc, PathStr};
+pub use prefix::PrefixStr;
+pub use rope::{StrRope, Chunks};
+pub use size::HeapSize;
+pub use small::SmallStr;
+#[cfg(feature = "std")]
+pub use symbol::{Symbol, SymbolTable, LocalSymbolTable};
+pub use weak::WeakStr;
+
+/// A string that stores up to `N` bytes inline; `Str` is the usual choice of `N`
 #[derive(Debug)]
-pub enum Str {
-    Small(SmallStr),
-    Rc(Arc<String>),
+pub enum StrN<const N: usize> {
+    Small(SmallStr<N>),
+    Rc(ArcStr),
     Static(&'static str),
+    Sub(SubStr),
+    /// A string shared with code that already holds it in an `Arc<String>`, kept without copying
+    ArcString(Arc<String>),
+}
+
+/// The default string type, storing up to 23 bytes inline
+///
+/// `Str` is the size of three pointers, and so is `Option<Str>`.
+pub type Str = StrN<23>;
+
+/// A substring of a reference-counted string that shares its parent's allocation
+///
+/// The offset and length are stored as `u32` so that `Str` stays the size of three pointers;
+/// slices of strings larger than 4 GiB are copied instead
+#[derive(Clone)]
+pub struct SubStr {
+    // the range always holds valid UTF-8, though the rest of the buffer needn't
+    rc: ArcBytes,
+    start: u32,
+    len: u32,
+}
+
+impl SubStr {
+    // the caller checks that `start..end` is valid UTF-8
+    fn new(rc: &ArcBytes, start: usize, end: usize) -> Option<SubStr> {
+        if end > u32::MAX as usize {
+            return None;
+        }
+        Some(SubStr {
+            rc: rc.clone(),
+            start: start as u32,
+            len: (end - start) as u32,
+        })
+    }
 }
 
-pub struct SmallStr {
-    len: u8,
-    bytes: [u8; 19],
-}
+impl Deref for SubStr {
+    type Target = str;
 
-impl Debug for SmallStr {
-    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::result::Result<(), std::fmt::Error> {
-        let s: &str = self.borrow();
-        Debug::fmt(s, f)
+    fn deref(&self) -> &str {
+        let start = self.start as usize;
+        unsafe { core::str::from_utf8_unchecked(&self.rc[start..start + self.len as usize]) }
     }
 }
 
-impl From<String> for SmallStr {
-    fn from(source: String) -> SmallStr {
-        let len = source.len() as usize;
-        let mut tstr = SmallStr {
-            len: len as u8,
-            bytes: [0; 19],
-        };
-        tstr.bytes[0..len].clone_from_slice(source.as_bytes());
-        tstr
+impl Debug for SubStr {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::result::Result<(), core::fmt::Error> {
+        Debug::fmt(&**self, f)
     }
 }
 
-impl From<Rc<String>> for SmallStr {
-    fn from(source: Rc<String>) -> SmallStr {
-        let s: String = Rc::try_unwrap(source).unwrap();
-        s.into()
+impl Display for SubStr {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::result::Result<(), core::fmt::Error> {
+        Display::fmt(&**self, f)
     }
 }
 
-impl Copy for SmallStr {
-}
-
-impl Clone for SmallStr {
-    fn clone(&self) -> Self {
-        SmallStr {
-            len: self.len,
-            bytes: self.bytes,
+impl<const N: usize> Display for StrN<N> {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::result::Result<(), core::fmt::Error> {
+        match *self {
+            StrN::Small(ref t) => Display::fmt(t, f),
+            StrN::Rc(ref rc) => Display::fmt(rc, f),
+            StrN::Static(s) => Display::fmt(s, f),
+            StrN::Sub(ref sub) => Display::fmt(sub, f),
+            StrN::ArcString(ref rc) => Display::fmt(rc, f),
         }
     }
 }
 
-impl Display for SmallStr {
-    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::result::Result<(), std::fmt::Error> {
-        let s: &str = self.borrow();
-        Display::fmt(s, f)
-    }
-}
+impl<const N: usize> Deref for StrN<N> {
+    type Target = str;
 
-impl Borrow<str> for SmallStr {
-    fn borrow(&self) -> &str {
-        let len = self.len as usize;
-        let bytes = &self.bytes[..len];
-        match from_utf8(bytes) {
-            Ok(s) => s,
-            Err(_) => "??",
+    fn deref(&self) -> &Self::Target {
+        match *self {
+            StrN::Small(ref t) => t.borrow(),
+            StrN::Rc(ref rc) => rc.as_str(),
+            StrN::Static(s) => s,
+            StrN::Sub(ref sub) => sub,
+            StrN::ArcString(ref rc) => rc,
         }
     }
 }
 
-impl Display for Str {
-    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::result::Result<(), std::fmt::Error> {
-        match self {
-            &Str::Small(ref t) => Display::fmt(t, f),
-            &Str::Rc(ref rc) => Display::fmt(rc, f),
-            &Str::Static(s) => Display::fmt(s, f),
-        }
+impl<const N: usize> StrN<N> {
+    /// Wraps a string literal without copying it; usable in `const` and `static` items
+    ///
+    /// Constants can't be used as match patterns, since `Str` compares contents rather than
+    /// representation, but they work in match guards: `s if s == DEFAULT => ...`
+    pub const fn from_static(s: &'static str) -> StrN<N> {
+        StrN::Static(s)
     }
-}
 
-impl Deref for Str {
-    type Target = str;
-
-    fn deref(&self) -> &Self::Target {
-        match self {
-            &Str::Small(ref t) => t.borrow(),
-            &Str::Rc(ref rc) => Deref::deref(rc),
-            &Str::Static(ref s) => s,
+    /// Stores a string literal inline if it fits and as `StrN::Static` otherwise, at compile time when
+    /// used in a const context; this is what `str!` expands to
+    pub const fn from_literal(s: &'static str) -> StrN<N> {
+        if s.len() <= SmallStr::<N>::CAPACITY {
+            StrN::Small(SmallStr::from_str_const(s))
+        } else {
+            StrN::Static(s)
         }
     }
-}
 
-impl Str {
     // This allows you to duplicate the original string
     // into a brand new owned String
     // It duplicates the memory and so it's a separate function you must opt into
@@ -169,20 +300,144 @@ impl Str {
         String::from(s)
     }
 
-    fn borrow_str(&self) -> &str {
+    /// Returns the substring in `range` without copying it
+    ///
+    /// Heap-backed strings share their allocation with the returned `Str`,
+    /// static strings stay static, and substrings short enough to be stored inline are copied into a `SmallStr`.
+    /// The exception is `StrN::ArcString`, which can't be shared by a slice: each substring too long
+    /// to be stored inline is copied into an allocation of its own.
+    ///
+    /// # Panics
+    ///
+    /// Panics if either end of `range` is out of bounds or not on a char boundary, just like slicing a `str`
+    pub fn slice<R: RangeBounds<usize>>(&self, range: R) -> StrN<N> {
+        let start = match range.start_bound() {
+            Bound::Included(&i) => i,
+            Bound::Excluded(&i) => i + 1,
+            Bound::Unbounded => 0,
+        };
+        let end = match range.end_bound() {
+            Bound::Included(&i) => i + 1,
+            Bound::Excluded(&i) => i,
+            Bound::Unbounded => self.len(),
+        };
+        let sub: &str = &self.borrow_str()[start..end];
+        if start == 0 && end == self.len() {
+            return self.clone();
+        }
+        if sub.len() <= SmallStr::<N>::CAPACITY {
+            return StrN::Small(SmallStr::copy_from(sub));
+        }
+        let shared = match *self {
+            StrN::Static(s) => Some(StrN::Static(&s[start..end])),
+            StrN::Rc(ref rc) => SubStr::new(rc.as_arc_bytes(), start, end).map(StrN::Sub),
+            StrN::Sub(ref parent) => {
+                let offset = parent.start as usize;
+                SubStr::new(&parent.rc, offset + start, offset + end).map(StrN::Sub)
+            }
+            StrN::Small(_) | StrN::ArcString(_) => None,
+        };
+        shared.unwrap_or_else(|| StrN::Rc(ArcStr::new(sub)))
+    }
+
+    /// Splits the string in two at byte index `mid`, sharing the allocation as `slice()` does
+    ///
+    /// As with `slice()`, the halves of a `StrN::ArcString` are copied unless they are stored inline.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `mid` is out of bounds or not on a char boundary
+    pub fn split_at(&self, mid: usize) -> (StrN<N>, StrN<N>) {
+        (self.slice(..mid), self.slice(mid..))
+    }
+
+    /// Truncates this string to `[0, at)` and returns the remainder, sharing the allocation as `slice()` does
+    ///
+    /// As with `slice()`, the halves of a `StrN::ArcString` are copied unless they are stored inline.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `at` is out of bounds or not on a char boundary
+    pub fn split_off(&mut self, at: usize) -> StrN<N> {
+        let (head, tail) = self.split_at(at);
+        *self = head;
+        tail
+    }
+
+    /// Converts a vector of bytes into a `Str`, failing if it isn't valid UTF-8
+    pub fn from_utf8(bytes: Vec<u8>) -> Result<StrN<N>, StrError> {
+        Ok(StrN::copy_from(from_utf8(&bytes)?))
+    }
+
+    /// Converts bytes into a `Str`, replacing invalid UTF-8 sequences with U+FFFD
+    pub fn from_utf8_lossy(bytes: &[u8]) -> StrN<N> {
+        StrN::copy_from(&String::from_utf8_lossy(bytes))
+    }
+
+    /// Takes back the `String` of a `StrN::ArcString` without copying
+    ///
+    /// Fails with `StrError::Shared` if the `Arc<String>` has other references,
+    /// and with `StrError::NotString` for the other variants, whose bytes aren't held in a `String` at all
+    pub fn try_into_string(self) -> Result<String, StrError> {
+        match self {
+            StrN::ArcString(rc) => Arc::try_unwrap(rc).map_err(|_| StrError::Shared),
+            _ => Err(StrError::NotString),
+        }
+    }
+
+    // the cheapest owned copy of `s`: inline if it fits, else a single exact-size allocation
+    pub(crate) fn copy_from(s: &str) -> StrN<N> {
+        if s.len() <= SmallStr::<N>::CAPACITY {
+            return StrN::Small(SmallStr::copy_from(s));
+        }
+        StrN::Rc(ArcStr::new(s))
+    }
+
+    /// Converts to a string with a different inline capacity
+    ///
+    /// Shared representations are kept as they are; inline strings are copied, to the heap if they no longer fit
+    pub fn into_capacity<const M: usize>(self) -> StrN<M> {
         match self {
-            &Str::Small(ref t) => t.borrow(),
-            &Str::Rc(ref s) => StrRef::borrow_str(s),
-            &Str::Static(s) => StrRef::borrow_str(s),
+            StrN::Small(t) => StrN::copy_from(&t),
+            StrN::Rc(rc) => StrN::Rc(rc),
+            StrN::Static(s) => StrN::Static(s),
+            StrN::Sub(sub) => StrN::Sub(sub),
+            StrN::ArcString(rc) => StrN::ArcString(rc),
+        }
+    }
+
+    fn borrow_str(&self) -> &str {
+        match *self {
+            StrN::Small(ref t) => t.borrow(),
+            StrN::Rc(ref s) => s.as_str(),
+            StrN::Static(s) => StrRef::borrow_str(s),
+            StrN::Sub(ref sub) => sub,
+            StrN::ArcString(ref rc) => rc,
         }
     }
 }
 
+#[cfg(feature = "std")]
+impl Str {
+    /// Returns a `Str` equal to `s` that shares its allocation with any equal string
+    /// already interned and still alive
+    ///
+    /// Strings short enough to be stored inline are returned as `Str::Small` and never enter the table
+    pub fn intern<S: StrRef>(s: S) -> Str {
+        intern::intern(s.borrow_str())
+    }
+
+    /// Returns the hit/miss counters of the global interner
+    pub fn intern_stats() -> InternStats {
+        intern::stats()
+    }
+}
+
 pub trait StrRef {
     fn borrow_str(&self) -> &str;
 }
 
-impl StrRef for Str{
+impl<const N: usize> StrRef for StrN<N> {
     fn borrow_str(&self) -> &str {
         self.borrow_str()
     }
@@ -202,9 +457,9 @@ impl StrRef for str {
     }
 }
 
-impl StrRef for &'static str {
+impl<T: StrRef + ?Sized> StrRef for &T {
     fn borrow_str(&self) -> &str {
-        self
+        (**self).borrow_str()
     }
 }
 
@@ -214,9 +469,9 @@ impl StrRef for String {
     }
 }
 
-impl<'f> StrRef for &'f String {
+impl StrRef for ArcStr {
     fn borrow_str(&self) -> &str {
-        (*self).borrow()
+        self.as_str()
     }
 }
 
@@ -232,140 +487,200 @@ pub trait ToStr : StrRef {
 }
 
 pub trait IntoStr : StrRef {
-    fn into_str(self) -> Str;
+    fn into_str(self) -> Str where Self: Sized;
+
+    /// Converts into a string that stores up to `N` bytes inline
+    ///
+    /// The default goes through `into_str()`; override it to copy straight into the inline storage of a `StrN<N>`.
+    fn into_str_n<const N: usize>(self) -> StrN<N> where Self: Sized {
+        self.into_str().into_capacity()
+    }
 }
 
-impl Clone for Str {
-    fn clone(&self) -> Str {
-        match self {
-            &Str::Small(t) => Str::Small(t),
-            &Str::Rc(ref s) => Str::Rc(s.clone()),
-            &Str::Static(s) => Str::Static(s),
+impl<const N: usize> Clone for StrN<N> {
+    fn clone(&self) -> StrN<N> {
+        match *self {
+            StrN::Small(t) => StrN::Small(t),
+            StrN::Rc(ref s) => StrN::Rc(s.clone()),
+            StrN::Static(s) => StrN::Static(s),
+            StrN::Sub(ref sub) => StrN::Sub(sub.clone()),
+            StrN::ArcString(ref rc) => StrN::ArcString(rc.clone()),
         }
     }
 }
 
-impl ToStr for Str {
+impl<const N: usize> ToStr for StrN<N> {
     fn to_str(&self) -> Str {
-        self.clone()
+        self.clone().into_capacity()
     }
 }
 
 impl ToStr for Arc<String> {
     fn to_str(&self) -> Str {
-        Str::Rc(self.clone())
+        StrN::ArcString(self.clone())
+    }
+}
+
+impl ToStr for ArcStr {
+    fn to_str(&self) -> Str {
+        StrN::Rc(self.clone())
     }
 }
 
 impl ToStr for &'static str {
     fn to_str(&self) -> Str {
-        Str::Static(*self)
+        StrN::Static(self)
+    }
+}
+
+impl<const N: usize> TryFrom<Vec<u8>> for StrN<N> {
+    type Error = StrError;
+
+    fn try_from(bytes: Vec<u8>) -> Result<StrN<N>, StrError> {
+        StrN::from_utf8(bytes)
     }
 }
 
-impl IntoStr for Str {
+impl<const N: usize> IntoStr for StrN<N> {
     fn into_str(self) -> Str {
-        self
+        self.into_capacity()
+    }
+
+    fn into_str_n<const M: usize>(self) -> StrN<M> {
+        self.into_capacity()
     }
 }
 
 impl IntoStr for String {
     fn into_str(self) -> Str {
-        if self.len() <= 19 { return Str::Small(self.into()); }
-        Str::Rc(Arc::new(self))
+        StrN::copy_from(&self)
+    }
+
+    fn into_str_n<const N: usize>(self) -> StrN<N> {
+        StrN::copy_from(&self)
     }
 }
 
-impl<'f> IntoStr for &'f String {
+impl IntoStr for &String {
     fn into_str(self) -> Str {
-        Str::Rc(Arc::new(self.clone()))
+        StrN::copy_from(self)
     }
 }
 
 impl IntoStr for Arc<String> {
     fn into_str(self) -> Str {
-        Str::Rc(self)
+        StrN::ArcString(self)
+    }
+}
+
+impl IntoStr for ArcStr {
+    fn into_str(self) -> Str {
+        StrN::Rc(self)
     }
 }
 
 impl IntoStr for Rc<String> {
     fn into_str(self) -> Str {
-        if self.len() <= 19 { return Str::Small(self.into()); }
+        StrN::copy_from(&self)
+    }
 
-        let s: &String = self.borrow();
-        let cloned = s.clone();
-        Str::Rc(Arc::new(cloned))
+    fn into_str_n<const N: usize>(self) -> StrN<N> {
+        StrN::copy_from(&self)
     }
 }
 
 impl IntoStr for &'static str {
     fn into_str(self) -> Str {
-        Str::Static(self)
+        StrN::Static(self)
     }
 }
 
-impl Borrow<str> for Str {
+impl<const N: usize> Borrow<str> for StrN<N> {
     fn borrow(&self) -> &str {
         self.borrow_str()
     }
 }
 
-impl PartialEq<Str> for str {
-    fn eq(&self, other: &Str) -> bool {
+impl<const N: usize> PartialEq<StrN<N>> for str {
+    fn eq(&self, other: &StrN<N>) -> bool {
         let s2: &str = other.borrow_str();
         self.eq(s2)
     }
 }
 
-impl PartialEq<Str> for &'static str {
-    fn eq(&self, other: &Str) -> bool {
+impl<const N: usize> PartialEq<StrN<N>> for &'static str {
+    fn eq(&self, other: &StrN<N>) -> bool {
         let s2: &str = other.borrow_str();
         (*self).eq(s2)
     }
 }
 
-impl PartialEq<str> for Str {
+impl<const N: usize> PartialEq<str> for StrN<N> {
     fn eq(&self, other: &str) -> bool {
         let s1: &str = self.borrow_str();
         s1.eq(other)
     }
 }
 
-impl PartialEq<Str> for Str {
-    fn eq(&self, other: &Str) -> bool {
-        let s2: &str = other.borrow_str();
-        self.eq(s2)
+impl<const N: usize> StrN<N> {
+    // true when both values are known to point at the same bytes without looking at them
+    fn same_bytes(&self, other: &StrN<N>) -> bool {
+        match (self, other) {
+            (StrN::Rc(a), StrN::Rc(b)) => ArcStr::ptr_eq(a, b),
+            (StrN::Static(a), StrN::Static(b)) => a.as_ptr() == b.as_ptr() && a.len() == b.len(),
+            (StrN::Sub(a), StrN::Sub(b)) => ArcBytes::ptr_eq(&a.rc, &b.rc) && a.start == b.start && a.len == b.len,
+            (StrN::ArcString(a), StrN::ArcString(b)) => Arc::ptr_eq(a, b),
+            _ => false,
+        }
     }
 }
 
-impl PartialOrd<Str> for Str {
-    fn partial_cmp(&self, other: &Str) -> Option<Ordering> {
-        let s1: &str = self.borrow_str();
-        let s2: &str = other.borrow_str();
-        s1.partial_cmp(s2)
+impl<const N: usize> PartialEq for StrN<N> {
+    fn eq(&self, other: &StrN<N>) -> bool {
+        if let (StrN::Small(a), StrN::Small(b)) = (self, other) {
+            return a == b;
+        }
+        if self.same_bytes(other) {
+            return true;
+        }
+        let (s1, s2) = (self.borrow_str(), other.borrow_str());
+        s1.len() == s2.len() && s1.as_bytes() == s2.as_bytes()
+    }
+}
+
+impl<const N: usize> PartialOrd for StrN<N> {
+    fn partial_cmp(&self, other: &StrN<N>) -> Option<Ordering> {
+        Some(self.cmp(other))
     }
 }
 
-impl Ord for Str {
-    fn cmp(&self, other: &Str) -> Ordering {
+impl<const N: usize> Ord for StrN<N> {
+    fn cmp(&self, other: &StrN<N>) -> Ordering {
+        if let (StrN::Small(a), StrN::Small(b)) = (self, other) {
+            return a.cmp(b);
+        }
+        if self.same_bytes(other) {
+            return Ordering::Equal;
+        }
         let s1: &str = self.borrow_str();
         let s2: &str = other.borrow_str();
         s1.cmp(s2)
     }
 }
 
-impl Hash for Str {
+impl<const N: usize> Hash for StrN<N> {
     fn hash<H: Hasher>(&self, h: &mut H) {
         let s: &str = self.borrow_str();
         s.hash(h)
     }
 }
 
-impl Eq for Str {}
+impl<const N: usize> Eq for StrN<N> {}
 
 #[cfg(test)]
 mod tests {
-    use super::{IntoStr, Str, SmallStr};
+    use super::{IntoStr, ToStr, Str, StrN, SmallStr, StrError};
+    use std::convert::TryFrom;
     use std::cmp::{Ordering};
     use std::sync::Arc;
     use std::rc::Rc;
@@ -378,6 +693,25 @@ mod tests {
     #[test]
     fn size() {
         assert_eq!(24, ::std::mem::size_of::<Str>());
+        assert_eq!(24, ::std::mem::size_of::<SmallStr>());
+        assert_eq!(23, <SmallStr>::new().capacity());
+    }
+
+    #[test]
+    fn niche() {
+        assert_eq!(24, ::std::mem::size_of::<Option<Str>>());
+        assert_eq!(24, ::std::mem::size_of::<Option<Option<Str>>>());
+        assert_eq!(24, ::std::mem::size_of::<Option<SmallStr>>());
+        assert_eq!(40, ::std::mem::size_of::<Option<StrN<36>>>());
+        // the spare values of the length don't change what is stored
+        let full: Option<Str> = Some("twenty-three bytes long".to_string().into_str());
+        match full {
+            Some(Str::Small(ref t)) => assert_eq!("twenty-three bytes long", t),
+            ref other => panic!("expected a small string, got {:?}", other),
+        }
+        let empty: Option<Str> = Some("".into_str());
+        assert_eq!(Some(""), empty.as_deref());
+        assert_eq!(None, None::<Str>.as_deref());
     }
 
     #[test]
@@ -391,7 +725,9 @@ mod tests {
         let ps = s.as_ptr();
         let ss = s.into_str();
         let ps2 = ss.as_ptr();
-        assert_eq!(ps, ps2);
+        // Strings are copied into a single exact-size allocation holding the counts and the bytes
+        assert_ne!(ps, ps2);
+        assert_eq!("String value that is too long to fit in small string", ss);
     }
 
     #[test]
@@ -407,6 +743,14 @@ mod tests {
         assert_eq!("String value", ss2);
     }
 
+    #[test]
+    fn pass_string_ref() {
+        let s = "String value".to_string();
+        assert!(matches!((&s).into_str(), Str::Small(_)));
+        let s = "String value that is too long to fit in small string".to_string();
+        assert!(matches!((&s).into_str(), Str::Rc(_)));
+    }
+
     #[test]
     fn pass_arc() {
         let s = "String value".to_string();
@@ -430,9 +774,205 @@ mod tests {
         assert_ne!(ps, ps2);
     }
 
+    fn shares_allocation(a: &Str, b: &Str) -> bool {
+        let range = a.as_ptr() as usize..a.as_ptr() as usize + a.len();
+        range.contains(&(b.as_ptr() as usize))
+    }
+
+    #[test]
+    fn slice_shares_allocation() {
+        let s = "String value that is too long to fit in small string".to_string().into_str();
+        let sub = s.slice(7..);
+        assert_eq!("value that is too long to fit in small string", sub);
+        assert!(shares_allocation(&s, &sub));
+        match sub {
+            Str::Sub(_) => (),
+            _ => panic!("expected a shared substring"),
+        }
+        let subsub = sub.slice(..=26);
+        assert_eq!("value that is too long to f", subsub);
+        assert!(shares_allocation(&s, &subsub));
+    }
+
+    #[test]
+    fn slice_small_and_static() {
+        let s = "String value that is too long to fit in small string".to_string().into_str();
+        match s.slice(7..12) {
+            Str::Small(t) => assert_eq!("value", t.to_string()),
+            _ => panic!("expected a small string"),
+        }
+        let st = "Static value that is too long to fit in small string".into_str();
+        match st.slice(7..) {
+            Str::Static(sub) => assert_eq!("value that is too long to fit in small string", sub),
+            _ => panic!("expected a static string"),
+        }
+    }
+
+    #[test]
+    fn slice_arc_string_copies() {
+        let s = Arc::new("String value that is too long to fit in small string".to_string()).into_str();
+        let sub = s.slice(7..);
+        assert_eq!("value that is too long to fit in small string", sub);
+        assert!(matches!(sub, Str::Rc(_)));
+        assert!(!shares_allocation(&s, &sub));
+        let (head, tail) = s.split_at(26);
+        assert!(matches!((&head, &tail), (Str::Rc(_), Str::Rc(_))));
+        assert!(!shares_allocation(&s, &head));
+        assert!(!shares_allocation(&s, &tail));
+        // the whole string is still shared
+        assert_eq!(s.as_ptr(), s.slice(..).as_ptr());
+    }
+
+    #[test]
+    fn split() {
+        let s = "A longer string containing ünïcödé characters".to_string().into_str();
+        let (head, tail) = s.split_at(27);
+        assert_eq!("A longer string containing ", head);
+        assert_eq!("ünïcödé characters", tail);
+        let mut s2 = s.clone();
+        let tail2 = s2.split_off(2);
+        assert_eq!("A ", s2);
+        assert_eq!(&s[2..], &*tail2);
+    }
+
+    #[test]
+    #[should_panic]
+    fn slice_off_char_boundary() {
+        "A longer string containing ünïcödé characters".to_string().into_str().slice(..28);
+    }
+
+    const DEFAULT: Str = Str::from_static("default");
+    static TABLE: [Str; 3] = [str!("short"), str!("a literal long enough to stay static"), DEFAULT];
+
+    #[test]
+    fn consts() {
+        match DEFAULT {
+            Str::Static(s) => assert_eq!("default", s),
+            ref other => panic!("expected a static string, got {:?}", other),
+        }
+        match TABLE[0] {
+            Str::Small(ref t) => assert_eq!("short", t.to_string()),
+            ref other => panic!("expected a small string, got {:?}", other),
+        }
+        match TABLE[1] {
+            Str::Static(s) => assert_eq!("a literal long enough to stay static", s),
+            ref other => panic!("expected a static string, got {:?}", other),
+        }
+        match TABLE[2] {
+            ref s if *s == DEFAULT => (),
+            ref other => panic!("expected the default, got {:?}", other),
+        }
+        const SMALL: SmallStr = SmallStr::from_str_const("twenty-three bytes long");
+        assert_eq!("twenty-three bytes long", SMALL.to_string());
+    }
+
+    #[test]
+    fn fast_paths_agree_with_str() {
+        let words = ["", "a", "a\0", "a\0b", "ab", "b", "twenty-three bytes long", "a string on the heap, longer than 23"];
+        let strs: Vec<Str> = words.iter().map(|w| w.to_string().into_str()).collect();
+        for (w1, s1) in words.iter().zip(&strs) {
+            for (w2, s2) in words.iter().zip(&strs) {
+                assert_eq!(w1 == w2, s1 == s2, "{:?} == {:?}", w1, w2);
+                assert_eq!(w1.cmp(w2), s1.cmp(s2), "{:?} cmp {:?}", w1, w2);
+            }
+        }
+        let shared = strs[7].clone();
+        assert_eq!(shared, strs[7]);
+        assert_eq!(Ordering::Equal, shared.cmp(&strs[7]));
+        let sub = shared.slice(1..);
+        assert_eq!(sub, shared.slice(1..));
+        assert_eq!(Str::Static("static"), Str::Static("static"));
+    }
+
+    #[test]
+    fn inline_capacity() {
+        const UUID: &str = "67e55044-10b1-426f-9247-bb680e5fe0c8";
+        let wide: StrN<36> = UUID.to_string().into_str_n();
+        match wide {
+            StrN::Small(ref t) => assert_eq!(36, t.capacity()),
+            ref other => panic!("expected a small string, got {:?}", other),
+        }
+        let narrow: Str = wide.clone().into_capacity();
+        match narrow {
+            Str::Rc(_) => (),
+            ref other => panic!("expected a heap string, got {:?}", other),
+        }
+        assert_eq!(wide, narrow.clone().into_capacity::<36>());
+        let heap = narrow.clone().into_capacity::<36>().into_capacity::<23>();
+        assert_eq!(narrow.as_ptr(), heap.as_ptr());
+        assert_eq!(UUID, wide.to_str());
+        assert!(SmallStr::<8>::try_from_str(UUID).is_err());
+        assert_eq!("short", <SmallStr>::try_from_str("short").unwrap().try_into_capacity::<8>().unwrap());
+    }
+
+    #[test]
+    fn into_str_is_enough_to_implement() {
+        // an impl written before inline capacities existed still compiles and gets `into_str_n` for free
+        struct Name(&'static str);
+
+        impl super::StrRef for Name {
+            fn borrow_str(&self) -> &str {
+                self.0
+            }
+        }
+
+        impl IntoStr for Name {
+            fn into_str(self) -> Str {
+                Str::Static(self.0)
+            }
+        }
+
+        assert_eq!("name", Name("name").into_str());
+        let wide: StrN<36> = Name("name").into_str_n();
+        assert_eq!("name", wide);
+    }
+
+    #[test]
+    fn small_try_from() {
+        let long = "String value that is too long to fit in small string";
+        assert_eq!(Err(StrError::TooLong { len: 52, capacity: 23 }),
+            <SmallStr>::try_from(long.to_string()).map(|t| t.to_string()));
+        assert_eq!(Err(StrError::TooLong { len: 52, capacity: 23 }),
+            <SmallStr>::try_from(Rc::new(long.to_string())).map(|t| t.to_string()));
+        // a shared Rc is copied rather than unwrapped
+        let rc = Rc::new("String value".to_string());
+        let _keep = rc.clone();
+        assert_eq!("String value", <SmallStr>::try_from(rc).unwrap().to_string());
+        assert_eq!("String value", <SmallStr>::try_from_str("String value").unwrap().to_string());
+        match <SmallStr>::try_from(&b"\xff"[..]) {
+            Err(StrError::InvalidUtf8(_)) => (),
+            other => panic!("expected invalid UTF-8, got {:?}", other),
+        }
+        // IntoStr for a shared Rc used to panic when unwrapping it
+        let rc = Rc::new("String value".to_string());
+        assert_eq!("String value", rc.clone().into_str());
+    }
+
+    #[test]
+    fn from_utf8() {
+        assert_eq!("valid", Str::from_utf8(b"valid".to_vec()).unwrap());
+        let err = Str::from_utf8(b"in\xffvalid".to_vec()).unwrap_err();
+        assert_eq!("invalid utf-8 sequence of 1 bytes from index 2", err.to_string());
+        assert!(::std::error::Error::source(&err).is_some());
+        assert_eq!("in\u{FFFD}valid", Str::from_utf8_lossy(b"in\xffvalid"));
+        assert_eq!("valid", Str::try_from(b"valid".to_vec()).unwrap());
+    }
+
+    #[test]
+    fn try_into_string() {
+        let s = "String value".to_string();
+        let ps = s.as_ptr();
+        let back = Arc::new(s).into_str().try_into_string().unwrap();
+        assert_eq!(ps, back.as_ptr());
+        let shared = Arc::new("String value".to_string());
+        assert_eq!(Err(StrError::Shared), shared.clone().into_str().try_into_string());
+        assert_eq!(Err(StrError::NotString), "String value".into_str().try_into_string());
+        assert_eq!(Err(StrError::NotString), "String value".to_string().into_str().try_into_string());
+    }
+
     #[test]
     fn debug_str_for_small() {
-        let t = SmallStr::from("String value".to_string());
+        let t = <SmallStr>::try_from("String value".to_string()).unwrap();
         assert_eq!("\"String value\"", format!("{:?}", t));
     }
 }
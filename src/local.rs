@@ -0,0 +1,248 @@
+//! Single-threaded string references
+//!
+//! `LocalStr` has the same representation as `Str` but counts references with `Rc`,
+//! so clones never pay for atomic operations and an existing `Rc<String>` can be taken as-is.
+//! It can't be sent to other threads; use `into_shared()` to get a `Str` for that.
+
+use alloc::rc::Rc;
+use alloc::string::String;
+use alloc::sync::Arc;
+use core::borrow::Borrow;
+use core::cmp::Ordering;
+use core::fmt::Display;
+use core::hash::{Hash, Hasher};
+use core::ops::Deref;
+
+use super::{Str, SmallStr, StrRef, ToStr, IntoStr};
+
+#[derive(Debug, Clone)]
+pub enum LocalStr {
+    Small(SmallStr),
+    Rc(Rc<String>),
+    Static(&'static str),
+}
+
+impl LocalStr {
+    /// Converts into a `Str` that can be shared between threads
+    ///
+    /// Small and static strings are moved as they are, as is the `String` of an `Rc` that isn't shared;
+    /// a shared `Rc<String>` is copied, and so is any string short enough to be stored inline
+    pub fn into_shared(self) -> Str {
+        match self {
+            LocalStr::Small(t) => Str::Small(t),
+            LocalStr::Static(s) => Str::Static(s),
+            LocalStr::Rc(rc) => match Rc::try_unwrap(rc) {
+                Ok(s) if s.len() > <SmallStr>::CAPACITY => Str::ArcString(Arc::new(s)),
+                Ok(s) => Str::copy_from(&s),
+                Err(rc) => Str::copy_from(&rc),
+            },
+        }
+    }
+
+    fn borrow_str(&self) -> &str {
+        match *self {
+            LocalStr::Small(ref t) => t.borrow(),
+            LocalStr::Rc(ref rc) => rc,
+            LocalStr::Static(s) => s,
+        }
+    }
+}
+
+impl Str {
+    /// Converts into a single-threaded `LocalStr`
+    ///
+    /// Small and static strings are moved as they are, as is an `Arc<String>` that isn't shared;
+    /// any other heap-backed string is copied
+    pub fn into_local(self) -> LocalStr {
+        match self {
+            Str::Small(t) => LocalStr::Small(t),
+            Str::Static(s) => LocalStr::Static(s),
+            Str::ArcString(rc) => match Arc::try_unwrap(rc) {
+                Ok(s) => LocalStr::Rc(Rc::new(s)),
+                Err(rc) => LocalStr::Rc(Rc::new(String::from(rc.as_str()))),
+            },
+            Str::Rc(_) | Str::Sub(_) => LocalStr::Rc(Rc::new(self.duplicate())),
+        }
+    }
+}
+
+impl From<Str> for LocalStr {
+    fn from(s: Str) -> LocalStr {
+        s.into_local()
+    }
+}
+
+impl From<LocalStr> for Str {
+    fn from(s: LocalStr) -> Str {
+        s.into_shared()
+    }
+}
+
+impl From<Rc<String>> for LocalStr {
+    fn from(rc: Rc<String>) -> LocalStr {
+        LocalStr::Rc(rc)
+    }
+}
+
+impl From<String> for LocalStr {
+    fn from(s: String) -> LocalStr {
+        if s.len() <= <SmallStr>::CAPACITY {
+            return LocalStr::Small(SmallStr::copy_from(&s));
+        }
+        LocalStr::Rc(Rc::new(s))
+    }
+}
+
+impl From<&'static str> for LocalStr {
+    fn from(s: &'static str) -> LocalStr {
+        LocalStr::Static(s)
+    }
+}
+
+impl StrRef for LocalStr {
+    fn borrow_str(&self) -> &str {
+        self.borrow_str()
+    }
+}
+
+impl ToStr for LocalStr {
+    fn to_str(&self) -> Str {
+        self.clone().into_shared()
+    }
+}
+
+impl IntoStr for LocalStr {
+    fn into_str(self) -> Str {
+        self.into_shared()
+    }
+}
+
+impl Display for LocalStr {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::result::Result<(), core::fmt::Error> {
+        Display::fmt(self.borrow_str(), f)
+    }
+}
+
+impl Deref for LocalStr {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        self.borrow_str()
+    }
+}
+
+impl Borrow<str> for LocalStr {
+    fn borrow(&self) -> &str {
+        self.borrow_str()
+    }
+}
+
+impl PartialEq<LocalStr> for str {
+    fn eq(&self, other: &LocalStr) -> bool {
+        self.eq(other.borrow_str())
+    }
+}
+
+impl PartialEq<LocalStr> for &'static str {
+    fn eq(&self, other: &LocalStr) -> bool {
+        (*self).eq(other.borrow_str())
+    }
+}
+
+impl PartialEq<str> for LocalStr {
+    fn eq(&self, other: &str) -> bool {
+        self.borrow_str().eq(other)
+    }
+}
+
+impl PartialEq<LocalStr> for LocalStr {
+    fn eq(&self, other: &LocalStr) -> bool {
+        self.borrow_str().eq(other.borrow_str())
+    }
+}
+
+impl Eq for LocalStr {}
+
+impl PartialOrd<LocalStr> for LocalStr {
+    fn partial_cmp(&self, other: &LocalStr) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for LocalStr {
+    fn cmp(&self, other: &LocalStr) -> Ordering {
+        self.borrow_str().cmp(other.borrow_str())
+    }
+}
+
+impl Hash for LocalStr {
+    fn hash<H: Hasher>(&self, h: &mut H) {
+        self.borrow_str().hash(h)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::LocalStr;
+    use super::super::{Str, IntoStr};
+    use std::rc::Rc;
+    use std::sync::Arc;
+
+    #[test]
+    fn size() {
+        assert_eq!(24, ::std::mem::size_of::<LocalStr>());
+    }
+
+    #[test]
+    fn pass_rc() {
+        let s = "String value".to_string();
+        let ps = s.as_ptr();
+        let rc = Rc::new(s);
+        let ls = LocalStr::from(rc.clone());
+        // unlike Str, LocalStr keeps the Rc<String> it was given
+        assert_eq!(ps, ls.as_ptr());
+        assert_eq!(2, Rc::strong_count(&rc));
+    }
+
+    #[test]
+    fn into_shared() {
+        let long = "String value that is too long to fit in small string";
+        let s = long.to_string();
+        let ps = s.as_ptr();
+        let shared = LocalStr::from(s).into_shared();
+        // an unshared Rc<String> gives up its String without copying
+        assert!(matches!(shared, Str::ArcString(_)));
+        assert_eq!(ps, shared.as_ptr());
+
+        let rc = Rc::new(long.to_string());
+        match LocalStr::from(rc.clone()).into_shared() {
+            Str::Rc(ref copy) => {
+                assert_eq!(long, &**copy);
+                assert_ne!(rc.as_ptr(), copy.as_ptr());
+            }
+            other => panic!("expected a heap string, got {:?}", other),
+        }
+        match LocalStr::from(Rc::new("String value".to_string())).into_shared() {
+            Str::Small(t) => assert_eq!("String value", t.to_string()),
+            other => panic!("expected a small string, got {:?}", other),
+        }
+        match LocalStr::from(long).into_str() {
+            Str::Static(s) => assert_eq!(long, s),
+            other => panic!("expected a static string, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn into_local() {
+        let s = "String value that is too long to fit in small string".to_string();
+        let ps = s.as_ptr();
+        let ls = Arc::new(s).into_str().into_local();
+        // an unshared Arc<String> gives up its String without copying
+        assert_eq!(ps, ls.as_ptr());
+
+        let shared = "String value that is too long to fit in small string".to_string().into_str();
+        let ls = shared.clone().into_local();
+        assert_ne!(shared.as_ptr(), ls.as_ptr());
+        assert_eq!(shared, *ls);
+    }
+}
@@ -0,0 +1,362 @@
+//! Cheaply cloned OS strings and paths
+//!
+//! `OsStrRc` stores the platform encoding of an `OsStr` in a `ByteStr`, so it gets the same
+//! inline, reference-counted and static representations and shares its buffer with `Str`.
+//! `PathStr` wraps it to stand in for `PathBuf` in indexes that clone paths often.
+
+use std::borrow::Borrow;
+use std::cmp::Ordering;
+use std::convert::TryFrom;
+use std::ffi::{OsStr, OsString};
+use std::fmt::{self, Debug};
+use std::hash::{Hash, Hasher};
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+
+use super::{Str, ByteStr, IntoByteStr, StrError};
+
+/// An immutable `OsStr` that is cheap to clone
+#[derive(Clone)]
+pub struct OsStrRc {
+    // always the output of `OsStr::as_encoded_bytes()`, or a range of it split at an ASCII character
+    bytes: ByteStr,
+}
+
+impl OsStrRc {
+    /// Copies `s`, inline if it fits or else into a single exact-size allocation
+    pub fn copy_from(s: &OsStr) -> OsStrRc {
+        OsStrRc { bytes: ByteStr::copy_from(s.as_encoded_bytes()) }
+    }
+
+    pub fn as_os_str(&self) -> &OsStr {
+        unsafe { OsStr::from_encoded_bytes_unchecked(&self.bytes) }
+    }
+
+    // shares the buffer when `part` lies inside it, as the results of `Path` methods do
+    fn share(&self, part: &OsStr) -> OsStrRc {
+        let whole = self.bytes.as_ptr() as usize;
+        let part_bytes = part.as_encoded_bytes();
+        let start = (part_bytes.as_ptr() as usize).wrapping_sub(whole);
+        match start.checked_add(part_bytes.len()) {
+            Some(end) if end <= self.bytes.len() => OsStrRc { bytes: self.bytes.slice(start..end) },
+            _ => OsStrRc::copy_from(part),
+        }
+    }
+}
+
+impl Deref for OsStrRc {
+    type Target = OsStr;
+
+    fn deref(&self) -> &OsStr {
+        self.as_os_str()
+    }
+}
+
+impl AsRef<OsStr> for OsStrRc {
+    fn as_ref(&self) -> &OsStr {
+        self.as_os_str()
+    }
+}
+
+impl AsRef<Path> for OsStrRc {
+    fn as_ref(&self) -> &Path {
+        Path::new(self.as_os_str())
+    }
+}
+
+impl Borrow<OsStr> for OsStrRc {
+    fn borrow(&self) -> &OsStr {
+        self.as_os_str()
+    }
+}
+
+impl From<OsString> for OsStrRc {
+    fn from(s: OsString) -> OsStrRc {
+        OsStrRc::copy_from(&s)
+    }
+}
+
+impl From<&'static OsStr> for OsStrRc {
+    fn from(s: &'static OsStr) -> OsStrRc {
+        OsStrRc { bytes: ByteStr::Static(s.as_encoded_bytes()) }
+    }
+}
+
+impl From<Str> for OsStrRc {
+    /// Shares the bytes of the string, since UTF-8 is valid in every platform encoding
+    fn from(s: Str) -> OsStrRc {
+        OsStrRc { bytes: s.into_byte_str() }
+    }
+}
+
+impl TryFrom<OsStrRc> for Str {
+    type Error = StrError;
+
+    /// Checks that the string is UTF-8 and shares its bytes with the returned `Str`
+    fn try_from(s: OsStrRc) -> Result<Str, StrError> {
+        Str::try_from(s.bytes)
+    }
+}
+
+impl Debug for OsStrRc {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        Debug::fmt(self.as_os_str(), f)
+    }
+}
+
+impl PartialEq for OsStrRc {
+    fn eq(&self, other: &OsStrRc) -> bool {
+        self.as_os_str() == other.as_os_str()
+    }
+}
+
+impl Eq for OsStrRc {}
+
+impl PartialEq<OsStr> for OsStrRc {
+    fn eq(&self, other: &OsStr) -> bool {
+        self.as_os_str() == other
+    }
+}
+
+impl PartialEq<str> for OsStrRc {
+    fn eq(&self, other: &str) -> bool {
+        self.as_os_str() == other
+    }
+}
+
+impl<'a> PartialEq<&'a str> for OsStrRc {
+    fn eq(&self, other: &&'a str) -> bool {
+        self.as_os_str() == *other
+    }
+}
+
+impl PartialOrd for OsStrRc {
+    fn partial_cmp(&self, other: &OsStrRc) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for OsStrRc {
+    fn cmp(&self, other: &OsStrRc) -> Ordering {
+        self.as_os_str().cmp(other.as_os_str())
+    }
+}
+
+impl Hash for OsStrRc {
+    fn hash<H: Hasher>(&self, h: &mut H) {
+        self.as_os_str().hash(h)
+    }
+}
+
+/// An immutable `Path` that is cheap to clone
+///
+/// Comparisons and hashing go through `Path`, so they work on components as they do for `PathBuf`.
+#[derive(Clone)]
+pub struct PathStr {
+    inner: OsStrRc,
+}
+
+impl PathStr {
+    /// Copies `path`, inline if it fits or else into a single exact-size allocation
+    pub fn copy_from(path: &Path) -> PathStr {
+        PathStr { inner: OsStrRc::copy_from(path.as_os_str()) }
+    }
+
+    pub fn as_path(&self) -> &Path {
+        Path::new(self.inner.as_os_str())
+    }
+
+    pub fn as_os_str_rc(&self) -> &OsStrRc {
+        &self.inner
+    }
+
+    pub fn into_os_str_rc(self) -> OsStrRc {
+        self.inner
+    }
+
+    /// `Path::parent()`, sharing this path's buffer
+    pub fn parent(&self) -> Option<PathStr> {
+        self.as_path().parent().map(|p| PathStr { inner: self.inner.share(p.as_os_str()) })
+    }
+
+    /// `Path::file_name()` as a relative path, sharing this path's buffer
+    pub fn file_name(&self) -> Option<PathStr> {
+        self.as_path().file_name().map(|name| PathStr { inner: self.inner.share(name) })
+    }
+
+    /// `Path::join()`, copying the result into a single exact-size allocation
+    pub fn join<P: AsRef<Path>>(&self, path: P) -> PathStr {
+        PathStr::from(self.as_path().join(path))
+    }
+}
+
+impl Deref for PathStr {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        self.as_path()
+    }
+}
+
+impl AsRef<Path> for PathStr {
+    fn as_ref(&self) -> &Path {
+        self.as_path()
+    }
+}
+
+impl AsRef<OsStr> for PathStr {
+    fn as_ref(&self) -> &OsStr {
+        self.inner.as_os_str()
+    }
+}
+
+impl Borrow<Path> for PathStr {
+    fn borrow(&self) -> &Path {
+        self.as_path()
+    }
+}
+
+impl From<PathBuf> for PathStr {
+    fn from(path: PathBuf) -> PathStr {
+        PathStr::copy_from(&path)
+    }
+}
+
+impl From<&'static Path> for PathStr {
+    fn from(path: &'static Path) -> PathStr {
+        PathStr { inner: OsStrRc::from(path.as_os_str()) }
+    }
+}
+
+impl From<&'static str> for PathStr {
+    fn from(path: &'static str) -> PathStr {
+        PathStr::from(Path::new(path))
+    }
+}
+
+impl From<Str> for PathStr {
+    fn from(s: Str) -> PathStr {
+        PathStr { inner: OsStrRc::from(s) }
+    }
+}
+
+impl From<OsStrRc> for PathStr {
+    fn from(s: OsStrRc) -> PathStr {
+        PathStr { inner: s }
+    }
+}
+
+impl TryFrom<PathStr> for Str {
+    type Error = StrError;
+
+    /// Checks that the path is UTF-8 and shares its bytes with the returned `Str`
+    fn try_from(path: PathStr) -> Result<Str, StrError> {
+        Str::try_from(path.inner)
+    }
+}
+
+impl Debug for PathStr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        Debug::fmt(self.as_path(), f)
+    }
+}
+
+impl PartialEq for PathStr {
+    fn eq(&self, other: &PathStr) -> bool {
+        self.as_path() == other.as_path()
+    }
+}
+
+impl Eq for PathStr {}
+
+impl PartialEq<Path> for PathStr {
+    fn eq(&self, other: &Path) -> bool {
+        self.as_path() == other
+    }
+}
+
+impl<'a> PartialEq<&'a Path> for PathStr {
+    fn eq(&self, other: &&'a Path) -> bool {
+        self.as_path() == *other
+    }
+}
+
+impl PartialOrd for PathStr {
+    fn partial_cmp(&self, other: &PathStr) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for PathStr {
+    fn cmp(&self, other: &PathStr) -> Ordering {
+        self.as_path().cmp(other.as_path())
+    }
+}
+
+impl Hash for PathStr {
+    fn hash<H: Hasher>(&self, h: &mut H) {
+        self.as_path().hash(h)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{OsStrRc, PathStr};
+    use super::super::{Str, IntoStr};
+    use std::collections::HashSet;
+    use std::convert::TryFrom;
+    use std::path::{Path, PathBuf};
+
+    #[test]
+    fn size() {
+        assert_eq!(24, ::std::mem::size_of::<PathStr>());
+    }
+
+    #[test]
+    fn parent_shares_buffer() {
+        let path = PathStr::from(PathBuf::from("/var/lib/some-service/state/data.db"));
+        let parent = path.parent().unwrap();
+        assert_eq!(parent, Path::new("/var/lib/some-service/state"));
+        assert_eq!(path.as_os_str().as_encoded_bytes().as_ptr(), parent.as_os_str().as_encoded_bytes().as_ptr());
+        let name = path.file_name().unwrap();
+        assert_eq!(name, Path::new("data.db"));
+        assert_eq!(None, PathStr::from("/").parent());
+    }
+
+    #[test]
+    fn join() {
+        let dir = PathStr::from("/var/lib");
+        assert_eq!(dir.join("service"), Path::new("/var/lib/service"));
+        assert_eq!(dir.join(Path::new("/etc")), Path::new("/etc"));
+    }
+
+    #[test]
+    fn to_str() {
+        let s = "some/relative/path/to/a/file.txt".to_string().into_str();
+        let path = PathStr::from(s.clone());
+        assert_eq!(Some("file.txt"), path.file_name().unwrap().to_str());
+        let back = Str::try_from(path).unwrap();
+        assert_eq!(s.as_ptr(), back.as_ptr());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn non_utf8() {
+        use std::ffi::OsStr;
+        use std::os::unix::ffi::OsStrExt;
+
+        let name = OsStr::from_bytes(b"/tmp/not-\xff-utf8/file");
+        let path = PathStr::copy_from(Path::new(name));
+        assert!(Str::try_from(path.clone()).is_err());
+        assert_eq!("file".into_str(), Str::try_from(path.file_name().unwrap()).unwrap());
+    }
+
+    #[test]
+    fn set_lookup() {
+        let mut set = HashSet::new();
+        set.insert(PathStr::from(PathBuf::from("/usr/share/doc/package/README")));
+        assert!(set.contains(Path::new("/usr/share/doc/package/README")));
+        let os: HashSet<OsStrRc> = set.iter().map(|p| p.as_os_str_rc().clone()).collect();
+        assert!(os.contains(Path::new("/usr/share/doc/package/README").as_os_str()));
+    }
+}
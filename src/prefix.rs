@@ -0,0 +1,331 @@
+//! Strings that keep their first bytes next to the length
+//!
+//! `PrefixStr` uses the 16-byte layout of Umbra's "German strings": a 4-byte length and the first
+//! 4 bytes of the string, followed by either the next 8 bytes or a pointer to the whole string.
+//! Most comparisons between unequal strings are settled by the prefix without following a pointer,
+//! which makes it a good fit for large vectors that are sorted and binary-searched.
+
+use alloc::string::String;
+use core::borrow::Borrow;
+use core::cmp::Ordering;
+use core::fmt::{self, Debug, Display};
+use core::hash::{Hash, Hasher};
+use core::mem::ManuallyDrop;
+use core::ops::Deref;
+use core::slice;
+use core::str;
+
+use super::{Str, StrN, ArcStr, StrRef, ToStr, IntoStr};
+
+const PREFIX_LEN: usize = 4;
+const INLINE_LEN: usize = 12;
+
+#[repr(C)]
+union Rest {
+    // bytes 4 to 12 of a string of up to `INLINE_LEN` bytes, padded with zeros
+    inline: [u8; INLINE_LEN - PREFIX_LEN],
+    // the whole string, for anything longer
+    heap: ManuallyDrop<ArcStr>,
+}
+
+/// A 16-byte immutable string that orders and compares by its first 4 bytes when it can
+///
+/// Strings of up to 12 bytes are stored inline; longer ones are shared with the `ArcStr` of a `Str`.
+///
+/// ```
+/// use strref::PrefixStr;
+///
+/// let mut names: Vec<PrefixStr> = vec!["zebra".into(), "anteater".into(), "a name stored on the heap".into()];
+/// names.sort();
+/// assert_eq!(Ok(1), names.binary_search(&"anteater".into()));
+/// ```
+#[repr(C)]
+pub struct PrefixStr {
+    len: u32,
+    // bytes past the end of a short string are zero, so prefixes compare as they would as strings
+    prefix: [u8; PREFIX_LEN],
+    rest: Rest,
+}
+
+impl PrefixStr {
+    /// Copies `s`, inline if it fits or else into a single exact-size allocation
+    ///
+    /// # Panics
+    ///
+    /// Panics if `s` is 4 GiB or longer
+    pub fn copy_from(s: &str) -> PrefixStr {
+        if s.len() <= INLINE_LEN {
+            let mut bytes = [0; INLINE_LEN];
+            bytes[..s.len()].copy_from_slice(s.as_bytes());
+            let mut inline = [0; INLINE_LEN - PREFIX_LEN];
+            inline.copy_from_slice(&bytes[PREFIX_LEN..]);
+            return PrefixStr {
+                len: s.len() as u32,
+                prefix: [bytes[0], bytes[1], bytes[2], bytes[3]],
+                rest: Rest { inline },
+            };
+        }
+        PrefixStr::from_arc_str(ArcStr::new(s))
+    }
+
+    fn from_arc_str(rc: ArcStr) -> PrefixStr {
+        assert!(rc.len() <= u32::MAX as usize, "string is too long for a PrefixStr");
+        let mut prefix = [0; PREFIX_LEN];
+        prefix.copy_from_slice(&rc.as_bytes()[..PREFIX_LEN]);
+        PrefixStr {
+            len: rc.len() as u32,
+            prefix,
+            rest: Rest { heap: ManuallyDrop::new(rc) },
+        }
+    }
+
+    pub fn as_str(&self) -> &str {
+        match self.heap() {
+            Some(rc) => rc.as_str(),
+            None => unsafe {
+                // with `repr(C)` the prefix and the inline bytes are contiguous, and the constructor
+                // copied them from a `&str`
+                let data = (self as *const PrefixStr as *const u8).add(PREFIX_LEN);
+                str::from_utf8_unchecked(slice::from_raw_parts(data, self.len as usize))
+            },
+        }
+    }
+
+    /// Returns true if the string is stored inline rather than on the heap
+    pub fn is_inline(&self) -> bool {
+        self.len as usize <= INLINE_LEN
+    }
+
+    fn heap(&self) -> Option<&ArcStr> {
+        if self.is_inline() {
+            return None;
+        }
+        unsafe { Some(&self.rest.heap) }
+    }
+
+    // only valid to call when the string is inline
+    fn inline_bytes(&self) -> &[u8; INLINE_LEN - PREFIX_LEN] {
+        unsafe { &self.rest.inline }
+    }
+}
+
+impl Drop for PrefixStr {
+    fn drop(&mut self) {
+        if !self.is_inline() {
+            unsafe { ManuallyDrop::drop(&mut self.rest.heap) }
+        }
+    }
+}
+
+impl Clone for PrefixStr {
+    fn clone(&self) -> PrefixStr {
+        let rest = match self.heap() {
+            Some(rc) => Rest { heap: ManuallyDrop::new(rc.clone()) },
+            None => Rest { inline: *self.inline_bytes() },
+        };
+        PrefixStr { len: self.len, prefix: self.prefix, rest }
+    }
+}
+
+impl Default for PrefixStr {
+    fn default() -> PrefixStr {
+        PrefixStr::copy_from("")
+    }
+}
+
+impl Deref for PrefixStr {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl AsRef<str> for PrefixStr {
+    fn as_ref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl Borrow<str> for PrefixStr {
+    fn borrow(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl<'a> From<&'a str> for PrefixStr {
+    fn from(s: &'a str) -> PrefixStr {
+        PrefixStr::copy_from(s)
+    }
+}
+
+impl From<String> for PrefixStr {
+    fn from(s: String) -> PrefixStr {
+        PrefixStr::copy_from(&s)
+    }
+}
+
+impl From<Str> for PrefixStr {
+    /// Shares the allocation of a `Str::Rc`; other strings longer than 12 bytes are copied
+    fn from(s: Str) -> PrefixStr {
+        match s {
+            StrN::Rc(rc) if rc.len() > INLINE_LEN => PrefixStr::from_arc_str(rc),
+            s => PrefixStr::copy_from(&s),
+        }
+    }
+}
+
+impl From<PrefixStr> for Str {
+    fn from(s: PrefixStr) -> Str {
+        s.into_str()
+    }
+}
+
+impl StrRef for PrefixStr {
+    fn borrow_str(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl ToStr for PrefixStr {
+    fn to_str(&self) -> Str {
+        self.clone().into_str()
+    }
+}
+
+impl IntoStr for PrefixStr {
+    fn into_str(self) -> Str {
+        self.into_str_n()
+    }
+
+    /// Shares the allocation of a heap-backed string
+    fn into_str_n<const N: usize>(self) -> StrN<N> {
+        match self.heap() {
+            Some(rc) => StrN::Rc(rc.clone()),
+            None => StrN::copy_from(self.as_str()),
+        }
+    }
+}
+
+impl Debug for PrefixStr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        Debug::fmt(self.as_str(), f)
+    }
+}
+
+impl Display for PrefixStr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        Display::fmt(self.as_str(), f)
+    }
+}
+
+impl PartialEq for PrefixStr {
+    fn eq(&self, other: &PrefixStr) -> bool {
+        if self.len != other.len || self.prefix != other.prefix {
+            return false;
+        }
+        match (self.heap(), other.heap()) {
+            (Some(a), Some(b)) => ArcStr::ptr_eq(a, b) || a.as_bytes()[PREFIX_LEN..] == b.as_bytes()[PREFIX_LEN..],
+            _ => self.inline_bytes() == other.inline_bytes(),
+        }
+    }
+}
+
+impl Eq for PrefixStr {}
+
+impl PartialEq<str> for PrefixStr {
+    fn eq(&self, other: &str) -> bool {
+        self.as_str() == other
+    }
+}
+
+impl<'a> PartialEq<&'a str> for PrefixStr {
+    fn eq(&self, other: &&'a str) -> bool {
+        self.as_str() == *other
+    }
+}
+
+impl PartialOrd for PrefixStr {
+    fn partial_cmp(&self, other: &PrefixStr) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for PrefixStr {
+    fn cmp(&self, other: &PrefixStr) -> Ordering {
+        // a zero byte of padding sorts before any byte of a longer string, so differing prefixes
+        // already order the strings
+        match self.prefix.cmp(&other.prefix) {
+            Ordering::Equal => (),
+            unequal => return unequal,
+        }
+        if let (Some(a), Some(b)) = (self.heap(), other.heap()) {
+            if ArcStr::ptr_eq(a, b) {
+                return Ordering::Equal;
+            }
+        }
+        self.as_bytes().cmp(other.as_bytes())
+    }
+}
+
+impl Hash for PrefixStr {
+    fn hash<H: Hasher>(&self, h: &mut H) {
+        self.as_str().hash(h)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::PrefixStr;
+    use super::super::{Str, IntoStr};
+    use std::collections::HashSet;
+
+    const WORDS: [&str; 10] = ["", "a", "a\0", "ab", "abcd", "abcd\0", "abcdefghijkl", "abcdefghijklm",
+        "abcdefghijklmnopqrstuvwxyz", "b"];
+
+    #[test]
+    fn size() {
+        assert_eq!(16, ::std::mem::size_of::<PrefixStr>());
+    }
+
+    #[test]
+    fn round_trip() {
+        for w in WORDS.iter() {
+            let s = PrefixStr::from(*w);
+            assert_eq!(*w, s.as_str());
+            assert_eq!(w.len() <= 12, s.is_inline());
+            assert_eq!(*w, s.clone().into_str());
+        }
+    }
+
+    #[test]
+    fn shares_heap_strings() {
+        let s = "a string long enough to be stored on the heap".to_string().into_str();
+        let p = PrefixStr::from(s.clone());
+        assert_eq!(s.as_ptr(), p.as_ptr());
+        let back = Str::from(p.clone());
+        assert_eq!(s.as_ptr(), back.as_ptr());
+        assert_eq!(p, PrefixStr::copy_from(&s));
+    }
+
+    #[test]
+    fn agrees_with_str() {
+        for w1 in WORDS.iter() {
+            for w2 in WORDS.iter() {
+                let (p1, p2) = (PrefixStr::from(*w1), PrefixStr::from(*w2));
+                assert_eq!(w1 == w2, p1 == p2, "{:?} == {:?}", w1, w2);
+                assert_eq!(w1.cmp(w2), p1.cmp(&p2), "{:?} cmp {:?}", w1, w2);
+            }
+        }
+        let set: HashSet<PrefixStr> = WORDS.iter().map(|&w| PrefixStr::from(w)).collect();
+        assert!(set.contains("abcdefghijklm"));
+    }
+
+    #[test]
+    fn sort() {
+        let mut words: Vec<PrefixStr> = (0..100).rev().map(|i| PrefixStr::from(format!("{:03} is a long key", i))).collect();
+        words.sort();
+        assert_eq!(words[0], "000 is a long key");
+        assert_eq!(Ok(42), words.binary_search(&PrefixStr::from("042 is a long key")));
+    }
+}
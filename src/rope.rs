@@ -0,0 +1,690 @@
+//! Persistent ropes of `Str` segments
+//!
+//! A `StrRope` is an AVL-balanced tree whose leaves are `Str` values.
+//! Concatenating, inserting and slicing share the existing segments (and the trees built from them)
+//! instead of copying bytes, and clones are as cheap as cloning one `Arc`.
+
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::cmp::Ordering;
+use core::fmt::{self, Display, Debug};
+use core::hash::{Hash, Hasher};
+use core::iter;
+use core::ops::{RangeBounds, Bound};
+
+use super::{Str, IntoStr, StrBuilder};
+
+type Link = Arc<Node>;
+
+enum Node {
+    Leaf {
+        s: Str,
+        chars: usize,
+    },
+    Branch {
+        left: Link,
+        right: Link,
+        len: usize,
+        chars: usize,
+        height: u8,
+    },
+}
+
+impl Node {
+    fn leaf(s: Str) -> Link {
+        let chars = s.chars().count();
+        Arc::new(Node::Leaf { s, chars })
+    }
+
+    // joins two trees of compatible heights without rebalancing
+    fn branch(left: Link, right: Link) -> Link {
+        Arc::new(Node::Branch {
+            len: left.len() + right.len(),
+            chars: left.chars() + right.chars(),
+            height: left.height().max(right.height()) + 1,
+            left,
+            right,
+        })
+    }
+
+    fn len(&self) -> usize {
+        match *self {
+            Node::Leaf { ref s, .. } => s.len(),
+            Node::Branch { len, .. } => len,
+        }
+    }
+
+    fn chars(&self) -> usize {
+        match *self {
+            Node::Leaf { chars, .. } | Node::Branch { chars, .. } => chars,
+        }
+    }
+
+    fn height(&self) -> u8 {
+        match *self {
+            Node::Leaf { .. } => 0,
+            Node::Branch { height, .. } => height,
+        }
+    }
+
+    fn children(&self) -> (&Link, &Link) {
+        match *self {
+            Node::Branch { ref left, ref right, .. } => (left, right),
+            Node::Leaf { .. } => unreachable!("a leaf has no children"),
+        }
+    }
+}
+
+// branch(a, branch(b, c)) => branch(branch(a, b), c)
+fn rotate_left(node: &Link) -> Link {
+    let (a, bc) = node.children();
+    let (b, c) = bc.children();
+    Node::branch(Node::branch(a.clone(), b.clone()), c.clone())
+}
+
+// branch(branch(a, b), c) => branch(a, branch(b, c))
+fn rotate_right(node: &Link) -> Link {
+    let (ab, c) = node.children();
+    let (a, b) = ab.children();
+    Node::branch(a.clone(), Node::branch(b.clone(), c.clone()))
+}
+
+// joins `left` onto a shorter `right` by descending the right spine of `left`
+fn join_right(left: &Link, right: Link) -> Link {
+    let (l, c) = left.children();
+    if c.height() <= right.height() + 1 {
+        let t = Node::branch(c.clone(), right);
+        if t.height() <= l.height() + 1 {
+            return Node::branch(l.clone(), t);
+        }
+        return rotate_left(&Node::branch(l.clone(), rotate_right(&t)));
+    }
+    let t = join_right(c, right);
+    let joined = Node::branch(l.clone(), t.clone());
+    if t.height() <= l.height() + 1 {
+        return joined;
+    }
+    rotate_left(&joined)
+}
+
+// joins a shorter `left` onto `right` by descending the left spine of `right`
+fn join_left(left: Link, right: &Link) -> Link {
+    let (c, r) = right.children();
+    if c.height() <= left.height() + 1 {
+        let t = Node::branch(left, c.clone());
+        if t.height() <= r.height() + 1 {
+            return Node::branch(t, r.clone());
+        }
+        return rotate_right(&Node::branch(rotate_left(&t), r.clone()));
+    }
+    let t = join_left(left, c);
+    let joined = Node::branch(t.clone(), r.clone());
+    if t.height() <= r.height() + 1 {
+        return joined;
+    }
+    rotate_right(&joined)
+}
+
+fn join(left: Link, right: Link) -> Link {
+    if left.height() > right.height() + 1 {
+        join_right(&left, right)
+    } else if right.height() > left.height() + 1 {
+        join_left(left, &right)
+    } else {
+        Node::branch(left, right)
+    }
+}
+
+fn join_opt(left: Option<Link>, right: Option<Link>) -> Option<Link> {
+    match (left, right) {
+        (Some(l), Some(r)) => Some(join(l, r)),
+        (l, None) => l,
+        (None, r) => r,
+    }
+}
+
+// splits at byte index `at`, which must be within the node
+fn split(node: &Link, at: usize) -> (Option<Link>, Option<Link>) {
+    if at == 0 {
+        return (None, Some(node.clone()));
+    }
+    if at == node.len() {
+        return (Some(node.clone()), None);
+    }
+    match **node {
+        Node::Leaf { ref s, chars } => {
+            // only the shorter half is counted; the other half's count follows from the leaf's
+            let (head, tail) = (s.slice(..at), s.slice(at..));
+            let (head_chars, tail_chars) = if at <= s.len() / 2 {
+                let n = head.chars().count();
+                (n, chars - n)
+            } else {
+                let n = tail.chars().count();
+                (chars - n, n)
+            };
+            (Some(Arc::new(Node::Leaf { s: head, chars: head_chars })), Some(Arc::new(Node::Leaf { s: tail, chars: tail_chars })))
+        }
+        Node::Branch { ref left, ref right, .. } => {
+            if at <= left.len() {
+                let (ll, lr) = split(left, at);
+                (ll, join_opt(lr, Some(right.clone())))
+            } else {
+                let (rl, rr) = split(right, at - left.len());
+                (join_opt(Some(left.clone()), rl), rr)
+            }
+        }
+    }
+}
+
+/// An immutable string made of `Str` segments in a balanced tree
+///
+/// ```
+/// use strref::{StrRope, IntoStr};
+///
+/// let rope = StrRope::from("Hello") + &StrRope::from(", world");
+/// let rope = rope.insert(5, "!".into_str());
+/// assert_eq!("Hello!, world", rope.to_string());
+/// assert_eq!("world", rope.slice(8..).flatten());
+/// ```
+#[derive(Clone, Default)]
+pub struct StrRope {
+    root: Option<Link>,
+}
+
+impl StrRope {
+    pub fn new() -> StrRope {
+        StrRope { root: None }
+    }
+
+    /// Returns the length in bytes
+    pub fn len(&self) -> usize {
+        self.root.as_ref().map_or(0, |n| n.len())
+    }
+
+    /// Returns the length in chars
+    pub fn char_len(&self) -> usize {
+        self.root.as_ref().map_or(0, |n| n.chars())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.root.is_none()
+    }
+
+    /// Returns a rope holding this rope followed by `other`
+    pub fn concat(&self, other: &StrRope) -> StrRope {
+        StrRope { root: join_opt(self.root.clone(), other.root.clone()) }
+    }
+
+    /// Appends `s` as a new segment, merging it into the last segment if both fit inline
+    pub fn push<S: IntoStr>(&mut self, s: S) {
+        let s = s.into_str();
+        if s.is_empty() {
+            return;
+        }
+        if let Some((merged, last_len)) = self.merge_last(&s) {
+            let (init, _) = self.split_at(self.len() - last_len);
+            self.root = join_opt(init.root, Some(Node::leaf(merged)));
+            return;
+        }
+        self.root = join_opt(self.root.take(), Some(Node::leaf(s)));
+    }
+
+    /// Returns a rope with `s` inserted at byte index `at`
+    ///
+    /// # Panics
+    ///
+    /// Panics if `at` is out of bounds or not on a char boundary
+    pub fn insert<S: IntoStr>(&self, at: usize, s: S) -> StrRope {
+        let s = s.into_str();
+        let (left, right) = self.split_at(at);
+        if s.is_empty() {
+            return left.concat(&right);
+        }
+        StrRope { root: join_opt(join_opt(left.root, Some(Node::leaf(s))), right.root) }
+    }
+
+    /// Splits the rope in two at byte index `at`
+    ///
+    /// # Panics
+    ///
+    /// Panics if `at` is out of bounds or not on a char boundary
+    pub fn split_at(&self, at: usize) -> (StrRope, StrRope) {
+        assert!(at <= self.len(), "byte index {} is out of bounds of a rope of {} bytes", at, self.len());
+        match self.root {
+            Some(ref root) => {
+                let (left, right) = split(root, at);
+                (StrRope { root: left }, StrRope { root: right })
+            }
+            None => (StrRope::new(), StrRope::new()),
+        }
+    }
+
+    /// Returns the bytes in `range` as a rope sharing this rope's segments
+    ///
+    /// # Panics
+    ///
+    /// Panics if either end of `range` is out of bounds or not on a char boundary
+    pub fn slice<R: RangeBounds<usize>>(&self, range: R) -> StrRope {
+        let start = match range.start_bound() {
+            Bound::Included(&i) => i,
+            Bound::Excluded(&i) => i + 1,
+            Bound::Unbounded => 0,
+        };
+        let end = match range.end_bound() {
+            Bound::Included(&i) => i + 1,
+            Bound::Excluded(&i) => i,
+            Bound::Unbounded => self.len(),
+        };
+        assert!(start <= end, "slice index starts at {} but ends at {}", start, end);
+        let (head, _) = self.split_at(end);
+        head.split_at(start).1
+    }
+
+    /// Returns the byte at byte index `i`
+    pub fn byte(&self, i: usize) -> Option<u8> {
+        let (s, offset) = self.leaf_at(i, Node::len)?;
+        Some(s.as_bytes()[offset])
+    }
+
+    /// Returns the char at char index `i`
+    pub fn char(&self, i: usize) -> Option<char> {
+        let (s, offset) = self.leaf_at(i, Node::chars)?;
+        s.chars().nth(offset)
+    }
+
+    /// Iterates over the segments of the rope in order
+    pub fn chunks(&self) -> Chunks<'_> {
+        Chunks { stack: self.root.iter().map(|n| &**n).collect() }
+    }
+
+    pub fn chars(&self) -> impl Iterator<Item = char> + '_ {
+        self.chunks().flat_map(str::chars)
+    }
+
+    /// Copies the rope into a single `Str`, allocating at most once
+    ///
+    /// A rope with a single segment returns a clone of that segment.
+    pub fn flatten(&self) -> Str {
+        let mut chunks = self.chunks();
+        match (chunks.next(), self.root.as_deref()) {
+            (None, _) => Str::Static(""),
+            (Some(_), Some(Node::Leaf { s, .. })) => s.clone(),
+            (Some(first), _) => {
+                let mut b = StrBuilder::with_capacity(self.len());
+                b.push_str(first);
+                chunks.for_each(|chunk| b.push_str(chunk));
+                b.finish()
+            }
+        }
+    }
+
+    // finds the leaf holding index `i`, measured with `measure`, and the index within it
+    fn leaf_at(&self, mut i: usize, measure: fn(&Node) -> usize) -> Option<(&Str, usize)> {
+        let mut node = self.root.as_ref()?;
+        if i >= measure(node) {
+            return None;
+        }
+        loop {
+            match **node {
+                Node::Leaf { ref s, .. } => return Some((s, i)),
+                Node::Branch { ref left, ref right, .. } => {
+                    if i < measure(left) {
+                        node = left;
+                    } else {
+                        i -= measure(left);
+                        node = right;
+                    }
+                }
+            }
+        }
+    }
+
+    // the last segment combined with `s` if both are stored inline and still fit together,
+    // along with the length of the last segment
+    fn merge_last(&self, s: &Str) -> Option<(Str, usize)> {
+        let mut node = self.root.as_ref()?;
+        while let Node::Branch { ref right, .. } = **node {
+            node = right;
+        }
+        match (&**node, s) {
+            (&Node::Leaf { s: Str::Small(mut last), .. }, &Str::Small(_)) => {
+                let last_len = last.len();
+                if last.try_push_str(s).is_ok() {
+                    return Some((Str::Small(last), last_len));
+                }
+                None
+            }
+            _ => None,
+        }
+    }
+}
+
+/// An iterator over the segments of a `StrRope`
+pub struct Chunks<'a> {
+    stack: Vec<&'a Node>,
+}
+
+impl<'a> Iterator for Chunks<'a> {
+    type Item = &'a str;
+
+    fn next(&mut self) -> Option<&'a str> {
+        let mut node = self.stack.pop()?;
+        loop {
+            match *node {
+                Node::Leaf { ref s, .. } => return Some(s),
+                Node::Branch { ref left, ref right, .. } => {
+                    self.stack.push(right);
+                    node = left;
+                }
+            }
+        }
+    }
+}
+
+impl<S: IntoStr> From<S> for StrRope {
+    fn from(s: S) -> StrRope {
+        let mut rope = StrRope::new();
+        rope.push(s);
+        rope
+    }
+}
+
+impl<'a> core::ops::Add<&'a StrRope> for StrRope {
+    type Output = StrRope;
+
+    fn add(self, other: &'a StrRope) -> StrRope {
+        self.concat(other)
+    }
+}
+
+impl Display for StrRope {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.chunks().try_for_each(|chunk| f.write_str(chunk))
+    }
+}
+
+impl Debug for StrRope {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("\"")?;
+        for chunk in self.chunks() {
+            for c in chunk.chars().flat_map(char::escape_debug) {
+                fmt::Write::write_char(f, c)?;
+            }
+        }
+        f.write_str("\"")
+    }
+}
+
+// compares two byte streams split into arbitrary chunks
+fn cmp_chunks<'a, 'b, A, B>(mut a: A, mut b: B) -> Ordering
+    where A: Iterator<Item = &'a str>, B: Iterator<Item = &'b str>
+{
+    let (mut x, mut y): (&[u8], &[u8]) = (&[], &[]);
+    loop {
+        while x.is_empty() {
+            match a.next() {
+                Some(chunk) => x = chunk.as_bytes(),
+                None => return if y.is_empty() && b.all(str::is_empty) { Ordering::Equal } else { Ordering::Less },
+            }
+        }
+        while y.is_empty() {
+            match b.next() {
+                Some(chunk) => y = chunk.as_bytes(),
+                None => return Ordering::Greater,
+            }
+        }
+        let n = x.len().min(y.len());
+        match x[..n].cmp(&y[..n]) {
+            Ordering::Equal => {
+                x = &x[n..];
+                y = &y[n..];
+            }
+            unequal => return unequal,
+        }
+    }
+}
+
+impl PartialEq for StrRope {
+    fn eq(&self, other: &StrRope) -> bool {
+        self.len() == other.len() && cmp_chunks(self.chunks(), other.chunks()) == Ordering::Equal
+    }
+}
+
+impl Eq for StrRope {}
+
+impl PartialEq<str> for StrRope {
+    fn eq(&self, other: &str) -> bool {
+        self.len() == other.len() && cmp_chunks(self.chunks(), iter::once(other)) == Ordering::Equal
+    }
+}
+
+impl PartialEq<Str> for StrRope {
+    fn eq(&self, other: &Str) -> bool {
+        self == &**other
+    }
+}
+
+impl PartialEq<StrRope> for Str {
+    fn eq(&self, other: &StrRope) -> bool {
+        other == &**self
+    }
+}
+
+impl PartialEq<StrRope> for &str {
+    fn eq(&self, other: &StrRope) -> bool {
+        other == *self
+    }
+}
+
+impl PartialOrd for StrRope {
+    fn partial_cmp(&self, other: &StrRope) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for StrRope {
+    fn cmp(&self, other: &StrRope) -> Ordering {
+        cmp_chunks(self.chunks(), other.chunks())
+    }
+}
+
+/// Hashes the same byte stream as `str`, fed to the hasher in fixed-size blocks
+///
+/// The blocks don't depend on where the segments meet, so equal ropes hash alike with any hasher,
+/// and a rope hashes like the equal `Str` with hashers that treat consecutive writes as one stream,
+/// such as std's `DefaultHasher`.
+impl Hash for StrRope {
+    fn hash<H: Hasher>(&self, h: &mut H) {
+        let mut block = [0; HASH_BLOCK];
+        let mut n = 0;
+        for chunk in self.chunks() {
+            let mut bytes = chunk.as_bytes();
+            while !bytes.is_empty() {
+                if n == HASH_BLOCK {
+                    h.write(&block);
+                    n = 0;
+                }
+                let take = bytes.len().min(HASH_BLOCK - n);
+                block[n..n + take].copy_from_slice(&bytes[..take]);
+                n += take;
+                bytes = &bytes[take..];
+            }
+        }
+        h.write(&block[..n]);
+        h.write_u8(0xff);
+    }
+}
+
+// a rope of at most this many bytes is written in one go, like a `str`
+const HASH_BLOCK: usize = 64;
+
+#[cfg(test)]
+mod tests {
+    use super::{StrRope, Node};
+    use super::super::{Str, IntoStr};
+    use std::collections::hash_map::DefaultHasher;
+    use std::hash::{Hash, Hasher};
+
+    fn words(n: usize) -> (StrRope, String) {
+        let mut rope = StrRope::new();
+        let mut expected = String::new();
+        for i in 0..n {
+            let word = format!("word number {} of the rope, ", i);
+            expected.push_str(&word);
+            rope.push(word);
+        }
+        (rope, expected)
+    }
+
+    fn height(rope: &StrRope) -> u8 {
+        rope.root.as_ref().map_or(0, |n| n.height())
+    }
+
+    fn assert_balanced(node: &Node) {
+        if let Node::Branch { ref left, ref right, height, .. } = *node {
+            assert!((left.height() as i32 - right.height() as i32).abs() <= 1);
+            assert_eq!(height, left.height().max(right.height()) + 1);
+            assert_balanced(left);
+            assert_balanced(right);
+        }
+    }
+
+    #[test]
+    fn concat_and_flatten() {
+        let (rope, expected) = words(1000);
+        assert_eq!(expected.len(), rope.len());
+        assert_eq!(expected, rope.to_string());
+        assert_eq!(&*expected, &*rope.flatten());
+        // an AVL tree of 1000 leaves is at most 1.44 * log2(1000) high
+        assert!(height(&rope) <= 15, "height {}", height(&rope));
+        assert_eq!(1000, rope.chunks().count());
+        assert_balanced(rope.root.as_ref().unwrap());
+    }
+
+    #[test]
+    fn shares_segments() {
+        let s = "a static segment that is long enough".into_str();
+        let rope = StrRope::from(s.clone()) + &StrRope::from(s.clone());
+        for chunk in rope.chunks() {
+            assert_eq!(s.as_ptr(), chunk.as_ptr());
+        }
+        let sliced = rope.slice(2..30);
+        assert_eq!(s[2..30].as_ptr(), sliced.chunks().next().unwrap().as_ptr());
+    }
+
+    #[test]
+    fn merges_small_segments() {
+        let mut rope = StrRope::new();
+        for c in "abcdefghij".chars() {
+            rope.push(c.to_string());
+        }
+        assert_eq!(1, rope.chunks().count());
+        assert_eq!("abcdefghij", rope.flatten());
+    }
+
+    #[test]
+    fn insert_and_slice() {
+        let (rope, mut expected) = words(200);
+        let inserted = rope.insert(1234, "[inserted text that is long]".into_str());
+        expected.insert_str(1234, "[inserted text that is long]");
+        assert_eq!(expected, inserted.to_string());
+        assert!(height(&inserted) <= 12);
+        for &(start, end) in &[(0, 0), (0, 10), (5, 1500), (1234, 1262), (3000, expected.len())] {
+            assert_eq!(&expected[start..end], inserted.slice(start..end).to_string());
+            let sliced = inserted.slice(start..end);
+            assert_eq!(end - start, sliced.len());
+            if let Some(ref root) = sliced.root {
+                assert_balanced(root);
+            }
+        }
+        // the original is untouched
+        assert_eq!(expected.len() - 28, rope.len());
+    }
+
+    #[test]
+    #[should_panic]
+    fn slice_off_char_boundary() {
+        StrRope::from("ünïcödé").slice(1..);
+    }
+
+    #[test]
+    fn indexing() {
+        let rope = StrRope::from("ünï") + &StrRope::from("cödé characters in a longer string");
+        assert_eq!(Some(b'n'), rope.byte(2));
+        assert_eq!(Some('c'), rope.char(3));
+        assert_eq!(Some('ö'), rope.char(4));
+        assert_eq!(None, rope.char(rope.char_len()));
+        assert_eq!(None, rope.byte(rope.len()));
+        assert_eq!(37, rope.char_len());
+        // splitting a leaf near either end keeps both halves' counts
+        let leaf = StrRope::from("ünïcödé characters in a longer string");
+        for &at in &[2, 3, 39] {
+            let (head, tail) = leaf.split_at(at);
+            assert_eq!(head.to_string().chars().count(), head.char_len());
+            assert_eq!(tail.to_string().chars().count(), tail.char_len());
+        }
+    }
+
+    #[test]
+    fn eq_cmp_and_hash() {
+        let a = StrRope::from("a string split ") + &StrRope::from("into several segments");
+        let b = StrRope::from("a string") + &StrRope::from(" split into several ") + &StrRope::from("segments");
+        let s: Str = "a string split into several segments".into_str();
+        assert_eq!(a, b);
+        assert_eq!(a, s);
+        assert_eq!(s, b);
+        let (after, before) = (StrRope::from("b"), StrRope::from("a string"));
+        assert!(a < after);
+        assert!(a > before);
+
+        let hash = |v: &dyn Fn(&mut DefaultHasher)| {
+            let mut h = DefaultHasher::new();
+            v(&mut h);
+            h.finish()
+        };
+        assert_eq!(hash(&|h| a.hash(h)), hash(&|h| s.hash(h)));
+        assert_eq!(hash(&|h| b.hash(h)), hash(&|h| s.hash(h)));
+    }
+
+    // a hasher that mixes each write in as a unit, so it tells apart the same bytes written in different pieces
+    #[derive(Default)]
+    struct PerWrite(u64);
+
+    impl Hasher for PerWrite {
+        fn write(&mut self, bytes: &[u8]) {
+            let mut hash = (self.0.rotate_left(5) ^ bytes.len() as u64).wrapping_mul(0x517c_c1b7_2722_0a95);
+            for &b in bytes {
+                hash = (hash ^ u64::from(b)).wrapping_mul(0x0100_0000_01b3);
+            }
+            self.0 = hash;
+        }
+
+        fn finish(&self) -> u64 {
+            self.0
+        }
+    }
+
+    #[test]
+    fn hash_ignores_segments() {
+        let hash = |rope: &StrRope| {
+            let mut h = PerWrite::default();
+            rope.hash(&mut h);
+            h.finish()
+        };
+        let whole = StrRope::from("hello world, this is a rope");
+        let mut pushed = StrRope::from("hello world, ");
+        pushed.push("this is a rope");
+        assert_eq!(whole, pushed);
+        assert_eq!(hash(&whole), hash(&pushed));
+        // a rope that fits in one block is written like the `str`
+        let mut h = PerWrite::default();
+        "hello world, this is a rope".hash(&mut h);
+        assert_eq!(h.finish(), hash(&pushed));
+
+        let (rope, expected) = words(40);
+        assert_eq!(hash(&StrRope::from(expected)), hash(&rope));
+        let (head, tail) = rope.split_at(100);
+        assert_eq!(hash(&rope), hash(&(head + &tail)));
+        assert_ne!(hash(&rope), hash(&rope.slice(1..)));
+    }
+}
@@ -0,0 +1,274 @@
+//! serde support, enabled with the `serde` feature
+//!
+//! `StrN` and `SmallStr` serialize as plain strings, whatever their inline capacity.
+//! Deserializing a `StrN<N>` keeps strings of up to `N` bytes inline and copies longer ones
+//! into a single exact-size allocation.
+//! Symbol tables serialize as the sequence of their strings in symbol order, and symbols as their numbers.
+
+use core::borrow::Borrow;
+use core::fmt;
+
+use serde::{Serialize, Serializer, Deserialize, Deserializer};
+use serde::de::{self, Visitor, Unexpected};
+
+use super::{StrN, SmallStr};
+#[cfg(feature = "std")]
+use super::{Str, Symbol, SymbolTable, LocalSymbolTable};
+
+impl<const N: usize> Serialize for StrN<N> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self)
+    }
+}
+
+impl<const N: usize> Serialize for SmallStr<N> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.borrow())
+    }
+}
+
+struct StrVisitor<const N: usize>;
+
+impl<const N: usize> StrVisitor<N> {
+    fn str_from_bytes<E: de::Error>(v: &[u8]) -> Result<StrN<N>, E> {
+        match core::str::from_utf8(v) {
+            Ok(s) => Ok(StrN::copy_from(s)),
+            Err(_) => Err(E::invalid_value(Unexpected::Bytes(v), &StrVisitor::<N>)),
+        }
+    }
+}
+
+impl<'de, const N: usize> Visitor<'de> for StrVisitor<N> {
+    type Value = StrN<N>;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a string")
+    }
+
+    fn visit_str<E: de::Error>(self, v: &str) -> Result<StrN<N>, E> {
+        Ok(StrN::copy_from(v))
+    }
+
+    fn visit_bytes<E: de::Error>(self, v: &[u8]) -> Result<StrN<N>, E> {
+        StrVisitor::str_from_bytes(v)
+    }
+}
+
+// only reachable through `Str::deserialize_static()`, where the input outlives the program
+struct StaticStrVisitor<const N: usize>;
+
+impl<const N: usize> Visitor<'static> for StaticStrVisitor<N> {
+    type Value = StrN<N>;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a string")
+    }
+
+    fn visit_borrowed_str<E: de::Error>(self, v: &'static str) -> Result<StrN<N>, E> {
+        Ok(StrN::Static(v))
+    }
+
+    fn visit_str<E: de::Error>(self, v: &str) -> Result<StrN<N>, E> {
+        Ok(StrN::copy_from(v))
+    }
+
+    fn visit_bytes<E: de::Error>(self, v: &[u8]) -> Result<StrN<N>, E> {
+        StrVisitor::str_from_bytes(v)
+    }
+}
+
+impl<'de, const N: usize> Deserialize<'de> for StrN<N> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<StrN<N>, D::Error> {
+        deserializer.deserialize_str(StrVisitor)
+    }
+}
+
+impl<const N: usize> StrN<N> {
+    /// Deserializes a `Str` from input that lives for the rest of the program,
+    /// keeping strings borrowed from the input as `Str::Static` instead of copying them
+    ///
+    /// Use it with `#[serde(deserialize_with = "Str::deserialize_static")]`
+    pub fn deserialize_static<D: Deserializer<'static>>(deserializer: D) -> Result<StrN<N>, D::Error> {
+        deserializer.deserialize_str(StaticStrVisitor)
+    }
+}
+
+struct SmallStrVisitor<const N: usize>;
+
+impl<'de, const N: usize> Visitor<'de> for SmallStrVisitor<N> {
+    type Value = SmallStr<N>;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "a string of at most {} bytes", N)
+    }
+
+    fn visit_str<E: de::Error>(self, v: &str) -> Result<SmallStr<N>, E> {
+        if v.len() > N {
+            return Err(E::invalid_length(v.len(), &self));
+        }
+        Ok(SmallStr::copy_from(v))
+    }
+
+    fn visit_bytes<E: de::Error>(self, v: &[u8]) -> Result<SmallStr<N>, E> {
+        match core::str::from_utf8(v) {
+            Ok(s) => self.visit_str(s),
+            Err(_) => Err(E::invalid_value(Unexpected::Bytes(v), &self)),
+        }
+    }
+}
+
+impl<'de, const N: usize> Deserialize<'de> for SmallStr<N> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<SmallStr<N>, D::Error> {
+        deserializer.deserialize_str(SmallStrVisitor)
+    }
+}
+
+#[cfg(feature = "std")]
+impl Serialize for Symbol {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u32(self.as_u32())
+    }
+}
+
+#[cfg(feature = "std")]
+impl<'de> Deserialize<'de> for Symbol {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Symbol, D::Error> {
+        match u32::deserialize(deserializer)? {
+            u32::MAX => Err(de::Error::invalid_value(Unexpected::Unsigned(u32::MAX.into()), &"a symbol number below u32::MAX")),
+            n => Ok(Symbol::from_u32(n)),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl Serialize for SymbolTable {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(self.iter().map(|(_, s)| s))
+    }
+}
+
+#[cfg(feature = "std")]
+impl Serialize for LocalSymbolTable {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(self.iter().map(|(_, s)| s))
+    }
+}
+
+#[cfg(feature = "std")]
+struct SymbolTableVisitor;
+
+#[cfg(feature = "std")]
+impl<'de> Visitor<'de> for SymbolTableVisitor {
+    type Value = LocalSymbolTable;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a sequence of distinct strings")
+    }
+
+    fn visit_seq<A: de::SeqAccess<'de>>(self, mut seq: A) -> Result<LocalSymbolTable, A::Error> {
+        let table = LocalSymbolTable::new();
+        while let Some(s) = seq.next_element::<Str>()? {
+            // a repeated string would shift the numbers of every symbol after it
+            let len = table.len();
+            if table.intern(s.clone()).as_u32() as usize != len {
+                return Err(de::Error::invalid_value(Unexpected::Str(&s), &"a string not already in the table"));
+            }
+        }
+        Ok(table)
+    }
+}
+
+#[cfg(feature = "std")]
+impl<'de> Deserialize<'de> for LocalSymbolTable {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<LocalSymbolTable, D::Error> {
+        deserializer.deserialize_seq(SymbolTableVisitor)
+    }
+}
+
+#[cfg(feature = "std")]
+impl<'de> Deserialize<'de> for SymbolTable {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<SymbolTable, D::Error> {
+        LocalSymbolTable::deserialize(deserializer).map(LocalSymbolTable::into_shared)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{Str, StrN, SmallStr, IntoStr};
+    #[cfg(feature = "std")]
+    use super::super::{Symbol, SymbolTable, LocalSymbolTable};
+    use serde_json;
+    use serde_test::{assert_tokens, assert_ser_tokens, assert_de_tokens, assert_de_tokens_error, Token};
+
+    #[test]
+    fn tokens() {
+        assert_tokens(&"short".into_str(), &[Token::Str("short")]);
+        assert_ser_tokens(&<SmallStr>::copy_from("short"), &[Token::Str("short")]);
+        assert_de_tokens(&"bytes".into_str(), &[Token::Bytes(b"bytes")]);
+        assert_de_tokens_error::<SmallStr>(
+            &[Token::Str("a string that is too long")],
+            "invalid length 25, expected a string of at most 23 bytes");
+        assert_de_tokens_error::<SmallStr<4>>(
+            &[Token::Str("short")],
+            "invalid length 5, expected a string of at most 4 bytes");
+    }
+
+    #[test]
+    fn picks_representation() {
+        let short: Str = serde_json::from_str("\"short\"").unwrap();
+        match short {
+            Str::Small(_) => (),
+            other => panic!("expected a small string, got {:?}", other),
+        }
+        let long: Str = serde_json::from_str("\"a string that is too long to be stored inline\"").unwrap();
+        match long {
+            Str::Rc(ref rc) => assert_eq!("a string that is too long to be stored inline", &**rc),
+            other => panic!("expected a heap string, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let values = vec!["short".into_str(), "a string that is too long to be stored inline".to_string().into_str()];
+        let json = serde_json::to_string(&values).unwrap();
+        assert_eq!("[\"short\",\"a string that is too long to be stored inline\"]", json);
+        let back: Vec<Str> = serde_json::from_str(&json).unwrap();
+        assert_eq!(values, back);
+        let small: SmallStr = serde_json::from_str("\"short\"").unwrap();
+        assert_eq!("short", small.to_string());
+        let uuid: StrN<36> = serde_json::from_str("\"67e55044-10b1-426f-9247-bb680e5fe0c8\"").unwrap();
+        match uuid {
+            StrN::Small(_) => (),
+            other => panic!("expected a small string, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn deserialize_static() {
+        static JSON: &str = "\"a string borrowed from static input\"";
+        let mut de = serde_json::Deserializer::from_str(JSON);
+        match <Str>::deserialize_static(&mut de).unwrap() {
+            Str::Static(s) => assert_eq!("a string borrowed from static input", s),
+            other => panic!("expected a static string, got {:?}", other),
+        }
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn symbol_table() {
+        let table = SymbolTable::new();
+        let syms = vec![table.intern("b"), table.intern("a"), table.intern("a symbol long enough to live on the heap")];
+        let json = serde_json::to_string(&(&table, &syms)).unwrap();
+        assert_eq!("[[\"b\",\"a\",\"a symbol long enough to live on the heap\"],[0,1,2]]", json);
+        let (back, back_syms): (SymbolTable, Vec<Symbol>) = serde_json::from_str(&json).unwrap();
+        assert_eq!(syms, back_syms);
+        assert_eq!("a", back.resolve(back_syms[1]));
+        assert_eq!(Some(back_syms[2]), back.lookup("a symbol long enough to live on the heap"));
+        assert_de_tokens_error::<LocalSymbolTable>(
+            &[Token::Seq { len: Some(2) }, Token::Str("a"), Token::Str("a"), Token::SeqEnd],
+            "invalid value: string \"a\", expected a string not already in the table");
+        assert_de_tokens_error::<Symbol>(
+            &[Token::U32(u32::MAX)],
+            "invalid value: integer `4294967295`, expected a symbol number below u32::MAX");
+    }
+}
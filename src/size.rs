@@ -0,0 +1,217 @@
+//! Heap-size accounting
+//!
+//! `heap_size()` is what a single string keeps alive on the heap, and `shared_heap_size()` its share
+//! of that when the allocation is shared. `HeapSize` totals many strings and counts each allocation
+//! once, however many strings share it, which is the figure to report for an index or a cache.
+
+use alloc::collections::BTreeSet;
+use alloc::string::String;
+use alloc::sync::Arc;
+use core::mem;
+
+use super::StrN;
+use heap::{ArcBytes, ArcStr};
+
+// an `Arc` allocation holds the strong and weak counts ahead of the value
+const ARC_COUNTS: usize = 2 * mem::size_of::<usize>();
+
+impl<const N: usize> StrN<N> {
+    /// Returns the number of heap bytes this string keeps alive, not counting the `StrN` itself
+    ///
+    /// Inline and static strings cost nothing. A slice keeps, and so counts, the whole allocation it was cut from.
+    ///
+    /// ```
+    /// use strref::{IntoStr, Str};
+    ///
+    /// assert_eq!(0, "short".to_string().into_str().heap_size());
+    /// assert_eq!(0, Str::from_static("a static string that is too long to be inlined").heap_size());
+    /// assert!("a runtime string that is too long to be inlined".to_string().into_str().heap_size() > 47);
+    /// ```
+    pub fn heap_size(&self) -> usize {
+        match *self {
+            StrN::Small(_) | StrN::Static(_) => 0,
+            StrN::Rc(ref rc) => ArcStr::allocation_size(rc),
+            StrN::Sub(ref sub) => ArcBytes::allocation_size(&sub.rc),
+            StrN::ArcString(ref rc) => ARC_COUNTS + mem::size_of::<String>() + rc.capacity(),
+        }
+    }
+
+    /// Returns `heap_size()` divided among the strings sharing the allocation
+    ///
+    /// Summed over every owner of an allocation this comes to its size, give or take rounding.
+    pub fn shared_heap_size(&self) -> usize {
+        match self.strong_count() {
+            0 | 1 => self.heap_size(),
+            n => self.heap_size() / n,
+        }
+    }
+
+    fn strong_count(&self) -> usize {
+        match *self {
+            StrN::Small(_) | StrN::Static(_) => 1,
+            StrN::Rc(ref rc) => ArcStr::strong_count(rc),
+            StrN::Sub(ref sub) => ArcBytes::strong_count(&sub.rc),
+            StrN::ArcString(ref rc) => Arc::strong_count(rc),
+        }
+    }
+
+    // identifies the allocation behind a heap-backed string
+    fn allocation(&self) -> Option<usize> {
+        match *self {
+            StrN::Small(_) | StrN::Static(_) => None,
+            StrN::Rc(ref rc) => Some(rc.as_ptr() as usize),
+            StrN::Sub(ref sub) => Some(sub.rc.as_ptr() as usize),
+            StrN::ArcString(ref rc) => Some(Arc::as_ptr(rc) as usize),
+        }
+    }
+}
+
+/// Totals the heap size of many strings, counting each shared allocation once
+///
+/// The storage of the collections holding the strings isn't included; add their capacity to taste.
+///
+/// ```
+/// use std::collections::HashMap;
+/// use strref::{HeapSize, IntoStr};
+///
+/// let name = "a name long enough to be stored on the heap".to_string().into_str();
+/// let names = vec![name.clone(), name.clone(), name.slice(2..)];
+/// let mut ids = HashMap::new();
+/// ids.insert(name.clone(), 1);
+///
+/// let mut size = HeapSize::new();
+/// size.add_all(&names);
+/// size.add_all(ids.keys());
+/// assert_eq!(name.heap_size(), size.total());
+/// assert_eq!(name.heap_size(), HeapSize::of(&names));
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct HeapSize {
+    seen: BTreeSet<usize>,
+    total: usize,
+}
+
+impl HeapSize {
+    pub fn new() -> HeapSize {
+        HeapSize::default()
+    }
+
+    /// Returns the heap size of all the strings, counting each allocation once
+    pub fn of<'a, I, const N: usize>(strs: I) -> usize
+        where I: IntoIterator<Item=&'a StrN<N>>
+    {
+        let mut size = HeapSize::new();
+        size.add_all(strs);
+        size.total()
+    }
+
+    /// Counts the allocation behind `s` unless it was already counted
+    pub fn add<const N: usize>(&mut self, s: &StrN<N>) {
+        if let Some(ptr) = s.allocation() {
+            if self.seen.insert(ptr) {
+                self.total += s.heap_size();
+            }
+        }
+    }
+
+    pub fn add_all<'a, I, const N: usize>(&mut self, strs: I)
+        where I: IntoIterator<Item=&'a StrN<N>>
+    {
+        for s in strs {
+            self.add(s);
+        }
+    }
+
+    /// Returns the number of heap bytes counted so far
+    pub fn total(&self) -> usize {
+        self.total
+    }
+}
+
+#[cfg(feature = "deepsize")]
+impl<const N: usize> ::deepsize::DeepSizeOf for StrN<N> {
+    /// Counts `shared_heap_size()`: deepsize can only track its own `Arc`s, so each owner counts its share
+    fn deep_size_of_children(&self, _: &mut ::deepsize::Context) -> usize {
+        self.shared_heap_size()
+    }
+}
+
+#[cfg(feature = "get-size")]
+impl<const N: usize> ::get_size::GetSize for StrN<N> {
+    /// Counts `heap_size()`, the whole allocation, as get-size does for an `Arc`
+    fn get_heap_size(&self) -> usize {
+        self.heap_size()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::HeapSize;
+    use super::super::{Str, IntoStr};
+    use std::mem;
+    use std::sync::Arc;
+
+    const LONG: &str = "a string long enough to live on the heap";
+
+    #[test]
+    fn heap_size() {
+        assert_eq!(0, String::new().into_str().heap_size());
+        assert_eq!(0, "short".to_string().into_str().heap_size());
+        assert_eq!(0, Str::from_static(LONG).heap_size());
+        // the counts and the length come first, and the allocation is padded to their alignment;
+        // slices count the whole allocation unless they are short enough to be copied inline
+        let rc = LONG.to_string().into_str();
+        assert_eq!((3 * mem::size_of::<usize>() + LONG.len()).next_multiple_of(mem::align_of::<usize>()), rc.heap_size());
+        assert_eq!(rc.heap_size(), rc.slice(1..).heap_size());
+        assert_eq!(0, rc.slice(30..).heap_size());
+        let mut s = String::with_capacity(100);
+        s.push_str(LONG);
+        let arc = Arc::new(s).into_str();
+        assert_eq!(2 * mem::size_of::<usize>() + mem::size_of::<String>() + 100, arc.heap_size());
+    }
+
+    #[test]
+    fn shared_heap_size() {
+        let rc = LONG.to_string().into_str();
+        let size = rc.heap_size();
+        assert_eq!(size, rc.shared_heap_size());
+        let sub = rc.slice(1..);
+        assert_eq!(size / 2, rc.shared_heap_size());
+        assert_eq!(size / 2, sub.shared_heap_size());
+        assert_eq!(0, Str::from_static(LONG).shared_heap_size());
+    }
+
+    #[test]
+    fn counts_allocations_once() {
+        let a = LONG.to_string().into_str();
+        let b = format!("{} too", LONG).into_str();
+        let arc = Arc::new(LONG.to_string()).into_str();
+        let strs = vec![a.clone(), a.slice(3..), b.clone(), "short".into_str(), arc.clone(), arc.clone()];
+        assert_eq!(a.heap_size() + b.heap_size() + arc.heap_size(), HeapSize::of(&strs));
+        let mut size = HeapSize::new();
+        size.add(&a);
+        size.add_all(&strs);
+        assert_eq!(HeapSize::of(&strs), size.total());
+        assert_eq!(0, HeapSize::of(&Vec::<Str>::new()));
+    }
+
+    #[cfg(feature = "deepsize")]
+    #[test]
+    fn deepsize() {
+        use deepsize::DeepSizeOf;
+        let rc = LONG.to_string().into_str();
+        let strs = vec![rc.clone(), rc.clone()];
+        assert_eq!(mem::size_of::<Str>() + rc.heap_size() / 3, rc.deep_size_of());
+        assert_eq!(mem::size_of::<Vec<Str>>() + strs.capacity() * mem::size_of::<Str>() + rc.heap_size() / 3 * 2,
+            strs.deep_size_of());
+    }
+
+    #[cfg(feature = "get-size")]
+    #[test]
+    fn get_size() {
+        use get_size::GetSize;
+        let rc = LONG.to_string().into_str();
+        assert_eq!(rc.heap_size(), rc.get_heap_size());
+        assert_eq!(mem::size_of::<Str>() + rc.heap_size(), rc.get_size());
+    }
+}
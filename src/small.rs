@@ -0,0 +1,406 @@
+//! Inline strings of up to `N` bytes
+//!
+//! `SmallStr` is the inline representation of `StrN`, and also usable on its own as a string on the stack.
+//! It is `Copy`, never allocates, and fails rather than grows when a string doesn't fit.
+
+use alloc::rc::Rc;
+use alloc::string::String;
+use core::borrow::Borrow;
+use core::cmp::Ordering;
+use core::convert::TryFrom;
+use core::fmt::{self, Debug, Display};
+use core::hash::{Hash, Hasher};
+use core::mem;
+use core::ops::Deref;
+use core::str::{self, FromStr};
+
+use super::StrError;
+
+// the length as an enum, so that the byte values a length never takes are free for `StrN` to
+// keep its discriminant in: that is what lets `Str` and `Option<Str>` both fit in 24 bytes
+// only `L0` is named, the rest are made by `Len::new()`
+#[allow(dead_code)]
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[repr(u8)]
+pub(crate) enum Len {
+    L0 = 0, L1, L2, L3, L4, L5, L6, L7,
+    L8, L9, L10, L11, L12, L13, L14, L15,
+    L16, L17, L18, L19, L20, L21, L22, L23,
+    L24, L25, L26, L27, L28, L29, L30, L31,
+    L32, L33, L34, L35, L36, L37, L38, L39,
+    L40, L41, L42, L43, L44, L45, L46, L47,
+    L48, L49, L50, L51, L52, L53, L54, L55,
+    L56, L57, L58, L59, L60, L61, L62, L63,
+    L64, L65, L66, L67, L68, L69, L70, L71,
+    L72, L73, L74, L75, L76, L77, L78, L79,
+    L80, L81, L82, L83, L84, L85, L86, L87,
+    L88, L89, L90, L91, L92, L93, L94, L95,
+    L96, L97, L98, L99, L100, L101, L102, L103,
+    L104, L105, L106, L107, L108, L109, L110, L111,
+    L112, L113, L114, L115, L116, L117, L118, L119,
+    L120, L121, L122, L123, L124, L125, L126, L127,
+}
+
+impl Len {
+    pub(crate) const MAX: usize = 127;
+
+    pub(crate) const fn new(len: usize) -> Len {
+        assert!(len <= Len::MAX, "length doesn't fit inline");
+        // sound because every value up to `MAX` is a variant
+        unsafe { mem::transmute::<u8, Len>(len as u8) }
+    }
+
+    pub(crate) const fn get(self) -> usize {
+        self as usize
+    }
+}
+
+/// A string of up to `N` bytes stored inline, 23 unless given otherwise
+///
+/// `N` can be at most 127.
+///
+/// ```
+/// use std::fmt::Write;
+/// use strref::SmallStr;
+///
+/// let mut s: SmallStr = SmallStr::new();
+/// write!(s, "{}-{}", "id", 42).unwrap();
+/// s.try_push('!').unwrap();
+/// assert_eq!("id-42!", s);
+/// assert!(s.try_push_str("more than there is room for").is_err());
+/// ```
+pub struct SmallStr<const N: usize = 23> {
+    // every constructor and mutator takes a whole `&str`, so `bytes[..len]` is always valid UTF-8
+    len: Len,
+    // bytes past `len` are always zero, so two strings can be compared as whole arrays
+    bytes: [u8; N],
+}
+
+impl<const N: usize> SmallStr<N> {
+    // every constructor goes through this, so a capacity that doesn't fit `Len` fails the build
+    pub(crate) const CAPACITY: usize = {
+        assert!(N <= Len::MAX, "SmallStr can hold at most 127 bytes");
+        N
+    };
+
+    // the caller checks that `source` fits
+    pub(crate) fn copy_from(source: &str) -> SmallStr<N> {
+        let mut tstr = SmallStr::new();
+        tstr.bytes[..source.len()].clone_from_slice(source.as_bytes());
+        tstr.len = Len::new(source.len());
+        tstr
+    }
+
+    pub const fn new() -> SmallStr<N> {
+        let _ = SmallStr::<N>::CAPACITY;
+        SmallStr { len: Len::L0, bytes: [0; N] }
+    }
+
+    /// Copies `source` into a `SmallStr` in a const context
+    ///
+    /// # Panics
+    ///
+    /// Panics if `source` is longer than `N` bytes, which fails the build when evaluated at compile time:
+    ///
+    /// ```compile_fail
+    /// use strref::SmallStr;
+    ///
+    /// const TOO_LONG: SmallStr = SmallStr::from_str_const("more than twenty-three bytes");
+    /// ```
+    pub const fn from_str_const(source: &str) -> SmallStr<N> {
+        let src = source.as_bytes();
+        if src.len() > SmallStr::<N>::CAPACITY {
+            panic!("string is too long for the inline storage of a SmallStr");
+        }
+        let mut bytes = [0; N];
+        let mut i = 0;
+        while i < src.len() {
+            bytes[i] = src[i];
+            i += 1;
+        }
+        SmallStr { len: Len::new(src.len()), bytes }
+    }
+
+    /// Copies `source` into a `SmallStr`, failing if it is longer than `N` bytes
+    pub fn try_from_str(source: &str) -> Result<SmallStr<N>, StrError> {
+        if source.len() > N {
+            return Err(StrError::TooLong { len: source.len(), capacity: N });
+        }
+        Ok(SmallStr::copy_from(source))
+    }
+
+    /// Copies the string into a `SmallStr` of a different capacity, failing if it doesn't fit
+    pub fn try_into_capacity<const M: usize>(&self) -> Result<SmallStr<M>, StrError> {
+        SmallStr::try_from_str(self.as_str())
+    }
+
+    /// Always `N` bytes
+    pub fn capacity(&self) -> usize {
+        N
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes[..self.len.get()]
+    }
+
+    /// UTF-8 is only checked on construction; debug builds and the `strict` feature check again
+    /// on every access and panic if the invariant was ever broken
+    pub fn as_str(&self) -> &str {
+        if cfg!(any(debug_assertions, feature = "strict")) {
+            return str::from_utf8(self.as_bytes()).expect("SmallStr holds invalid UTF-8");
+        }
+        unsafe { str::from_utf8_unchecked(self.as_bytes()) }
+    }
+
+    /// Appends `c`, leaving the string untouched if it doesn't fit
+    pub fn try_push(&mut self, c: char) -> Result<(), StrError> {
+        self.try_push_str(c.encode_utf8(&mut [0; 4]))
+    }
+
+    /// Appends `s`, leaving the string untouched if it doesn't fit
+    pub fn try_push_str(&mut self, s: &str) -> Result<(), StrError> {
+        let len = self.len.get();
+        if len + s.len() > N {
+            return Err(StrError::TooLong { len: len + s.len(), capacity: N });
+        }
+        self.bytes[len..len + s.len()].clone_from_slice(s.as_bytes());
+        self.len = Len::new(len + s.len());
+        Ok(())
+    }
+
+    /// Shortens the string to `new_len` bytes, doing nothing if it is already that short
+    ///
+    /// # Panics
+    ///
+    /// Panics if `new_len` is not on a char boundary, just like `String::truncate()`
+    pub fn truncate(&mut self, new_len: usize) {
+        if new_len >= self.len.get() {
+            return;
+        }
+        assert!(self.as_str().is_char_boundary(new_len), "new_len is not on a char boundary");
+        for b in &mut self.bytes[new_len..self.len.get()] {
+            *b = 0;
+        }
+        self.len = Len::new(new_len);
+    }
+
+    pub fn clear(&mut self) {
+        self.truncate(0)
+    }
+}
+
+impl<const N: usize> Default for SmallStr<N> {
+    fn default() -> SmallStr<N> {
+        SmallStr::new()
+    }
+}
+
+impl<const N: usize> Copy for SmallStr<N> {
+}
+
+impl<const N: usize> Clone for SmallStr<N> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<const N: usize> Deref for SmallStr<N> {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl<const N: usize> AsRef<str> for SmallStr<N> {
+    fn as_ref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl<const N: usize> Borrow<str> for SmallStr<N> {
+    fn borrow(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl<const N: usize> FromStr for SmallStr<N> {
+    type Err = StrError;
+
+    fn from_str(s: &str) -> Result<SmallStr<N>, StrError> {
+        SmallStr::try_from_str(s)
+    }
+}
+
+impl<'a, const N: usize> TryFrom<&'a str> for SmallStr<N> {
+    type Error = StrError;
+
+    fn try_from(source: &'a str) -> Result<SmallStr<N>, StrError> {
+        SmallStr::try_from_str(source)
+    }
+}
+
+impl<'a, const N: usize> TryFrom<&'a [u8]> for SmallStr<N> {
+    type Error = StrError;
+
+    fn try_from(source: &'a [u8]) -> Result<SmallStr<N>, StrError> {
+        SmallStr::try_from_str(str::from_utf8(source)?)
+    }
+}
+
+impl<const N: usize> TryFrom<String> for SmallStr<N> {
+    type Error = StrError;
+
+    fn try_from(source: String) -> Result<SmallStr<N>, StrError> {
+        SmallStr::try_from_str(&source)
+    }
+}
+
+impl<const N: usize> TryFrom<Rc<String>> for SmallStr<N> {
+    type Error = StrError;
+
+    /// Copies the string out of the `Rc`, whether or not it is shared
+    fn try_from(source: Rc<String>) -> Result<SmallStr<N>, StrError> {
+        SmallStr::try_from_str(&source)
+    }
+}
+
+impl<const N: usize> fmt::Write for SmallStr<N> {
+    /// Fails with `fmt::Error` once the output no longer fits, keeping what was written before
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.try_push_str(s).map_err(|_| fmt::Error)
+    }
+}
+
+impl<const N: usize> Debug for SmallStr<N> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        Debug::fmt(self.as_str(), f)
+    }
+}
+
+impl<const N: usize> Display for SmallStr<N> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        Display::fmt(self.as_str(), f)
+    }
+}
+
+impl<const N: usize> PartialEq for SmallStr<N> {
+    fn eq(&self, other: &SmallStr<N>) -> bool {
+        self.len == other.len && self.bytes == other.bytes
+    }
+}
+
+impl<const N: usize> Eq for SmallStr<N> {}
+
+impl<const N: usize> PartialEq<str> for SmallStr<N> {
+    fn eq(&self, other: &str) -> bool {
+        self.as_bytes() == other.as_bytes()
+    }
+}
+
+impl<'a, const N: usize> PartialEq<&'a str> for SmallStr<N> {
+    fn eq(&self, other: &&'a str) -> bool {
+        self.as_bytes() == other.as_bytes()
+    }
+}
+
+impl<const N: usize> PartialEq<SmallStr<N>> for str {
+    fn eq(&self, other: &SmallStr<N>) -> bool {
+        self.as_bytes() == other.as_bytes()
+    }
+}
+
+impl<const N: usize> PartialEq<SmallStr<N>> for &str {
+    fn eq(&self, other: &SmallStr<N>) -> bool {
+        self.as_bytes() == other.as_bytes()
+    }
+}
+
+impl<const N: usize> PartialOrd for SmallStr<N> {
+    fn partial_cmp(&self, other: &SmallStr<N>) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<const N: usize> Ord for SmallStr<N> {
+    fn cmp(&self, other: &SmallStr<N>) -> Ordering {
+        // the zero padding sorts before any byte, so only equal arrays need the lengths to break the tie
+        self.bytes.cmp(&other.bytes).then(self.len.cmp(&other.len))
+    }
+}
+
+impl<const N: usize> Hash for SmallStr<N> {
+    fn hash<H: Hasher>(&self, h: &mut H) {
+        self.as_str().hash(h)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SmallStr;
+    use super::super::StrError;
+    use std::collections::HashSet;
+    use std::fmt::Write;
+
+    #[test]
+    fn push() {
+        let mut s: SmallStr = SmallStr::new();
+        assert_eq!(23, s.capacity());
+        s.try_push_str("ünïcödé").unwrap();
+        s.try_push('!').unwrap();
+        assert_eq!("ünïcödé!", s);
+        assert_eq!(Err(StrError::TooLong { len: 32, capacity: 23 }), s.try_push_str("still more than fits"));
+        assert_eq!("ünïcödé!", s.as_str());
+    }
+
+    #[test]
+    fn truncate() {
+        let mut s: SmallStr = "ünïcödé".parse().unwrap();
+        s.truncate(3);
+        assert_eq!("ün", s);
+        // the bytes cut off don't linger and affect comparisons
+        assert_eq!(SmallStr::from_str_const("ün"), s);
+        s.clear();
+        assert!(s.is_empty());
+        assert_eq!(SmallStr::default(), s);
+    }
+
+    #[test]
+    #[should_panic(expected = "char boundary")]
+    fn truncate_off_char_boundary() {
+        <SmallStr>::from_str_const("ünïcödé").truncate(1);
+    }
+
+    #[test]
+    #[should_panic(expected = "invalid UTF-8")]
+    #[cfg(any(debug_assertions, feature = "strict"))]
+    fn corrupt_bytes_panic() {
+        let mut s: SmallStr = SmallStr::from_str_const("ok");
+        s.bytes[0] = 0xff;
+        let _ = s.len();
+    }
+
+    #[test]
+    fn fmt_write() {
+        let mut s: SmallStr = SmallStr::new();
+        write!(s, "{}+{}", 19, 23).unwrap();
+        assert_eq!("19+23", s);
+        let tail = "a tail that overflows it";
+        assert!(write!(s, "{}", tail).is_err());
+        assert_eq!("19+23", s);
+    }
+
+    #[test]
+    fn ord_and_hash_agree_with_str() {
+        let words = ["", "a", "a\0", "ab", "b", "twenty-three bytes long"];
+        for w1 in words.iter() {
+            for w2 in words.iter() {
+                let (s1, s2): (SmallStr, SmallStr) = (SmallStr::from_str_const(w1), SmallStr::from_str_const(w2));
+                assert_eq!(w1.cmp(w2), s1.cmp(&s2), "{:?} cmp {:?}", w1, w2);
+            }
+        }
+        let set: HashSet<SmallStr> = words.iter().map(|w| w.parse().unwrap()).collect();
+        assert!(set.contains("ab"));
+        assert!("more than twenty-three bytes".parse::<SmallStr>().is_err());
+    }
+}
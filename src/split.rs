@@ -0,0 +1,163 @@
+//! Splitting into owned pieces
+//!
+//! These are the splitting methods of `str`, yielding `Str` pieces that live on after the parent is dropped.
+//! Each piece is a `slice()` of its parent: heap-backed parents share their allocation with the pieces,
+//! static parents yield static pieces, and pieces short enough to be stored inline are copied.
+//! An `Arc<String>` parent is copied once into an allocation of its own, the first time a piece needs the heap.
+
+use super::{StrN, SmallStr, ArcStr};
+
+impl<const N: usize> StrN<N> {
+    /// Splits on `sep`, like `str::split`
+    ///
+    /// ```
+    /// use strref::{IntoStr, Str};
+    ///
+    /// let csv = "id,a name long enough to be stored on the heap,42".to_string().into_str();
+    /// let fields: Vec<Str> = csv.split_str(",").collect();
+    /// assert_eq!(vec!["id", "a name long enough to be stored on the heap", "42"], fields);
+    /// assert!(matches!(fields[1], Str::Sub(_)));
+    /// ```
+    pub fn split_str<'a>(&'a self, sep: &'a str) -> impl Iterator<Item = StrN<N>> + 'a {
+        self.pieces(self.borrow_str().split(sep))
+    }
+
+    /// Splits on `sep` into at most `n` pieces, like `str::splitn`
+    pub fn splitn_str<'a>(&'a self, n: usize, sep: &'a str) -> impl Iterator<Item = StrN<N>> + 'a {
+        self.pieces(self.borrow_str().splitn(n, sep))
+    }
+
+    /// Splits on `sep`, skipping an empty piece after a trailing separator, like `str::split_terminator`
+    pub fn split_terminator_str<'a>(&'a self, sep: &'a str) -> impl Iterator<Item = StrN<N>> + 'a {
+        self.pieces(self.borrow_str().split_terminator(sep))
+    }
+
+    /// Splits into lines ending in `\n` or `\r\n`, without the line endings, like `str::lines`
+    pub fn lines_str(&self) -> impl Iterator<Item = StrN<N>> + '_ {
+        self.pieces(self.borrow_str().lines())
+    }
+
+    /// Splits on runs of Unicode whitespace, like `str::split_whitespace`
+    pub fn split_whitespace_str(&self) -> impl Iterator<Item = StrN<N>> + '_ {
+        self.pieces(self.borrow_str().split_whitespace())
+    }
+
+    /// Returns the runs of consecutive chars that match `is_token`, skipping everything in between
+    ///
+    /// ```
+    /// use strref::{IntoStr, Str};
+    ///
+    /// let src = "let total = price * 2;".into_str();
+    /// let idents: Vec<Str> = src.tokens(|c| c.is_alphanumeric() || c == '_').collect();
+    /// assert_eq!(vec!["let", "total", "price", "2"], idents);
+    /// ```
+    pub fn tokens<'a, F>(&'a self, mut is_token: F) -> impl Iterator<Item = StrN<N>> + 'a
+        where F: FnMut(char) -> bool + 'a
+    {
+        let s = self.borrow_str();
+        let mut chars = s.char_indices().peekable();
+        let runs = core::iter::from_fn(move || {
+            let start = loop {
+                let (i, c) = chars.next()?;
+                if is_token(c) {
+                    break i;
+                }
+            };
+            let mut end = s.len();
+            while let Some(&(i, c)) = chars.peek() {
+                if !is_token(c) {
+                    end = i;
+                    break;
+                }
+                chars.next();
+            }
+            Some(&s[start..end])
+        });
+        self.pieces(runs)
+    }
+
+    // turns pieces borrowed from this string into slices of it
+    fn pieces<'a, I>(&'a self, pieces: I) -> impl Iterator<Item = StrN<N>> + 'a
+        where I: Iterator<Item = &'a str> + 'a
+    {
+        let base = self.borrow_str().as_ptr() as usize;
+        let mut copied = None;
+        pieces.map(move |piece| {
+            let start = piece.as_ptr() as usize - base;
+            let range = start..start + piece.len();
+            match *self {
+                StrN::ArcString(ref rc) if piece.len() > SmallStr::<N>::CAPACITY =>
+                    copied.get_or_insert_with(|| StrN::Rc(ArcStr::new(rc))).slice(range),
+                _ => self.slice(range),
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{Str, IntoStr};
+    use std::sync::Arc;
+
+    const LONG: &str = "the first line is long enough to live on the heap\r\nshort\n\nthe last line is also long enough for the heap\n";
+
+    fn shares(piece: &Str, parent: &Str) -> bool {
+        let (p, range) = (piece.as_ptr() as usize, parent.as_ptr() as usize..parent.as_ptr() as usize + parent.len());
+        range.contains(&p)
+    }
+
+    #[test]
+    fn agrees_with_str() {
+        let s = LONG.to_string().into_str();
+        assert_eq!(LONG.split('\n').collect::<Vec<_>>(), s.split_str("\n").collect::<Vec<_>>());
+        assert_eq!(LONG.splitn(2, "\r\n").collect::<Vec<_>>(), s.splitn_str(2, "\r\n").collect::<Vec<_>>());
+        assert_eq!(LONG.split_terminator('\n').collect::<Vec<_>>(), s.split_terminator_str("\n").collect::<Vec<_>>());
+        assert_eq!(LONG.lines().collect::<Vec<_>>(), s.lines_str().collect::<Vec<_>>());
+        assert_eq!(LONG.split_whitespace().collect::<Vec<_>>(), s.split_whitespace_str().collect::<Vec<_>>());
+        assert_eq!(vec!["", ""], Str::from_static("").split_str("").collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn shares_the_parent() {
+        let s = LONG.to_string().into_str();
+        let lines: Vec<Str> = s.lines_str().collect();
+        assert!(matches!(lines[0], Str::Sub(_)));
+        assert!(shares(&lines[0], &s));
+        assert!(matches!(lines[1], Str::Small(_)));
+        // splitting a piece still points into the original allocation
+        let rest = lines[3].split_str("last ").nth(1).unwrap();
+        assert_eq!("line is also long enough for the heap", rest);
+        assert!(matches!(rest, Str::Sub(_)));
+        assert!(shares(&rest, &s));
+    }
+
+    #[test]
+    fn static_pieces() {
+        let s = Str::from_static(LONG);
+        for line in s.lines_str() {
+            assert!(matches!(line, Str::Static(_) | Str::Small(_)));
+        }
+        assert!(matches!(s.lines_str().next(), Some(Str::Static(l)) if l.as_ptr() == LONG.as_ptr()));
+    }
+
+    #[test]
+    fn arc_string_is_copied_once() {
+        let s = Arc::new(LONG.to_string()).into_str();
+        let lines: Vec<Str> = s.lines_str().collect();
+        assert_eq!(LONG.lines().collect::<Vec<_>>(), lines);
+        assert!(!shares(&lines[0], &s));
+        assert!(matches!((&lines[0], &lines[3]), (Str::Sub(_), Str::Sub(_))));
+        let offset = LONG.find("the last").unwrap();
+        assert_eq!(lines[0].as_ptr() as usize + offset, lines[3].as_ptr() as usize);
+    }
+
+    #[test]
+    fn tokens() {
+        let s = "  fn main() { let x_1 = 10; }".into_str();
+        let tokens: Vec<Str> = s.tokens(|c| c.is_alphanumeric() || c == '_').collect();
+        assert_eq!(vec!["fn", "main", "let", "x_1", "10"], tokens);
+        assert_eq!(0, s.tokens(|c| c == '#').count());
+        let s = "héllo wörld".into_str();
+        assert_eq!(vec!["héllo", "wörld"], s.tokens(|c| !c.is_whitespace()).collect::<Vec<_>>());
+    }
+}
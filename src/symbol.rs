@@ -0,0 +1,318 @@
+//! Symbol tables
+//!
+//! A `Symbol` is a 4-byte handle to a string in a symbol table, so comparing and hashing one
+//! never touches the string. Symbols are numbered from zero in the order their strings were interned.
+//! `SymbolTable` can be shared between threads; `LocalSymbolTable` is for a single thread and
+//! skips the locking.
+
+use alloc::vec::Vec;
+use core::cell::RefCell;
+use core::convert::TryFrom;
+use core::num::NonZeroU32;
+use std::collections::HashMap;
+use std::sync::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+use super::{Str, IntoStr};
+
+/// A handle to a string interned in a `SymbolTable` or `LocalSymbolTable`
+///
+/// Symbols from different tables can't be told apart, so only resolve a symbol with the table that made it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Symbol {
+    // one more than the index, so that `Option<Symbol>` is still 4 bytes
+    id: NonZeroU32,
+}
+
+impl Symbol {
+    /// Returns the symbol numbered `n`, the `n`th string interned in its table
+    ///
+    /// # Panics
+    ///
+    /// Panics if `n` is `u32::MAX`
+    pub fn from_u32(n: u32) -> Symbol {
+        let id = n.checked_add(1).and_then(NonZeroU32::new).expect("too many symbols");
+        Symbol { id }
+    }
+
+    pub fn as_u32(self) -> u32 {
+        self.id.get() - 1
+    }
+
+    fn index(self) -> usize {
+        self.as_u32() as usize
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+struct Symbols {
+    strs: Vec<Str>,
+    ids: HashMap<Str, Symbol>,
+}
+
+impl Symbols {
+    fn lookup(&self, s: &str) -> Option<Symbol> {
+        self.ids.get(s).copied()
+    }
+
+    fn insert(&mut self, s: Str) -> Symbol {
+        let sym = Symbol::from_u32(u32::try_from(self.strs.len()).expect("too many symbols"));
+        self.strs.push(s.clone());
+        self.ids.insert(s, sym);
+        sym
+    }
+
+    fn intern<S: IntoStr>(&mut self, s: S) -> Symbol {
+        match self.lookup(s.borrow_str()) {
+            Some(sym) => sym,
+            None => self.insert(s.into_str()),
+        }
+    }
+
+    fn get(&self, sym: Symbol) -> Option<Str> {
+        self.strs.get(sym.index()).cloned()
+    }
+}
+
+fn foreign_symbol(sym: Symbol) -> ! {
+    panic!("symbol {} is not in this table", sym.as_u32())
+}
+
+/// A symbol table that can be shared between threads
+///
+/// ```
+/// use strref::SymbolTable;
+///
+/// let table = SymbolTable::new();
+/// let a = table.intern("main");
+/// let b = table.intern("main".to_string());
+/// assert_eq!(a, b);
+/// assert_eq!("main", table.resolve(a));
+/// ```
+#[derive(Debug, Default)]
+pub struct SymbolTable {
+    symbols: RwLock<Symbols>,
+}
+
+impl SymbolTable {
+    pub fn new() -> SymbolTable {
+        SymbolTable::default()
+    }
+
+    /// Returns the symbol for `s`, adding it to the table if it isn't there yet
+    ///
+    /// Static strings are kept as they are, without copying.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the table already holds `u32::MAX` strings
+    pub fn intern<S: IntoStr>(&self, s: S) -> Symbol {
+        if let Some(sym) = self.read().lookup(s.borrow_str()) {
+            return sym;
+        }
+        self.write().intern(s)
+    }
+
+    /// Returns the symbol for `s` if it has been interned
+    pub fn lookup(&self, s: &str) -> Option<Symbol> {
+        self.read().lookup(s)
+    }
+
+    /// Returns the string of a symbol from this table
+    ///
+    /// # Panics
+    ///
+    /// Panics if the symbol is out of range for this table
+    pub fn resolve(&self, sym: Symbol) -> Str {
+        self.get(sym).unwrap_or_else(|| foreign_symbol(sym))
+    }
+
+    /// Returns the string of a symbol from this table, or `None` if it is out of range
+    pub fn get(&self, sym: Symbol) -> Option<Str> {
+        self.read().get(sym)
+    }
+
+    pub fn len(&self) -> usize {
+        self.read().strs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Iterates over the symbols and their strings in the order they were interned
+    ///
+    /// Strings interned while iterating are left out; the table isn't locked in between items.
+    pub fn iter(&self) -> impl Iterator<Item = (Symbol, Str)> + '_ {
+        (0..self.len() as u32).map(move |n| {
+            let sym = Symbol::from_u32(n);
+            (sym, self.resolve(sym))
+        })
+    }
+
+    // the table is consistent between statements, so a poisoned lock is still usable
+    fn read(&self) -> RwLockReadGuard<'_, Symbols> {
+        self.symbols.read().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn write(&self) -> RwLockWriteGuard<'_, Symbols> {
+        self.symbols.write().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+/// A symbol table for a single thread, such as one kept in a `thread_local!`
+///
+/// ```
+/// use strref::{LocalSymbolTable, Symbol};
+///
+/// thread_local! {
+///     static SYMBOLS: LocalSymbolTable = LocalSymbolTable::new();
+/// }
+///
+/// let sym: Symbol = SYMBOLS.with(|t| t.intern("x"));
+/// assert_eq!("x", SYMBOLS.with(|t| t.resolve(sym)));
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct LocalSymbolTable {
+    symbols: RefCell<Symbols>,
+}
+
+impl LocalSymbolTable {
+    pub fn new() -> LocalSymbolTable {
+        LocalSymbolTable::default()
+    }
+
+    /// Returns the symbol for `s`, adding it to the table if it isn't there yet
+    ///
+    /// Static strings are kept as they are, without copying.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the table already holds `u32::MAX` strings
+    pub fn intern<S: IntoStr>(&self, s: S) -> Symbol {
+        self.symbols.borrow_mut().intern(s)
+    }
+
+    /// Returns the symbol for `s` if it has been interned
+    pub fn lookup(&self, s: &str) -> Option<Symbol> {
+        self.symbols.borrow().lookup(s)
+    }
+
+    /// Returns the string of a symbol from this table
+    ///
+    /// # Panics
+    ///
+    /// Panics if the symbol is out of range for this table
+    pub fn resolve(&self, sym: Symbol) -> Str {
+        self.get(sym).unwrap_or_else(|| foreign_symbol(sym))
+    }
+
+    /// Returns the string of a symbol from this table, or `None` if it is out of range
+    pub fn get(&self, sym: Symbol) -> Option<Str> {
+        self.symbols.borrow().get(sym)
+    }
+
+    pub fn len(&self) -> usize {
+        self.symbols.borrow().strs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Iterates over the symbols and their strings in the order they were interned
+    ///
+    /// Strings interned while iterating are left out; the table isn't borrowed in between items.
+    pub fn iter(&self) -> impl Iterator<Item = (Symbol, Str)> + '_ {
+        (0..self.len() as u32).map(move |n| {
+            let sym = Symbol::from_u32(n);
+            (sym, self.resolve(sym))
+        })
+    }
+
+    /// Moves the strings into a table that can be shared between threads, keeping their symbols
+    pub fn into_shared(self) -> SymbolTable {
+        SymbolTable { symbols: RwLock::new(self.symbols.into_inner()) }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Symbol, SymbolTable, LocalSymbolTable};
+    use super::super::{Str, IntoStr};
+    use std::mem;
+    use std::sync::Arc;
+    use std::thread;
+
+    const LONG: &str = "a symbol long enough to live on the heap";
+
+    #[test]
+    fn size() {
+        assert_eq!(4, mem::size_of::<Symbol>());
+        assert_eq!(4, mem::size_of::<Option<Symbol>>());
+        assert_eq!(7, Symbol::from_u32(7).as_u32());
+    }
+
+    #[test]
+    fn intern_and_resolve() {
+        let table = LocalSymbolTable::new();
+        let a = table.intern(LONG.to_string());
+        let b = table.intern("b");
+        assert_eq!(a, table.intern(LONG));
+        assert_ne!(a, b);
+        assert_eq!(LONG, table.resolve(a));
+        assert_eq!(Some(b), table.lookup("b"));
+        assert_eq!(None, table.lookup("c"));
+        assert_eq!(None, table.get(Symbol::from_u32(2)));
+        assert_eq!(2, table.len());
+        // resolving shares the stored string
+        assert_eq!(table.resolve(a).as_ptr(), table.resolve(a).as_ptr());
+    }
+
+    #[test]
+    fn keeps_static_strings() {
+        let table = SymbolTable::new();
+        let sym = table.intern(LONG);
+        assert!(matches!(table.resolve(sym), Str::Static(s) if s.as_ptr() == LONG.as_ptr()));
+        // the first string interned is the one kept
+        table.intern(LONG.to_string().into_str());
+        assert!(matches!(table.resolve(sym), Str::Static(_)));
+    }
+
+    #[test]
+    fn insertion_order() {
+        let table = LocalSymbolTable::new();
+        for w in ["c", "a", "b", "a"].iter() {
+            table.intern(*w);
+        }
+        let strs: Vec<(u32, Str)> = table.iter().map(|(sym, s)| (sym.as_u32(), s)).collect();
+        assert_eq!(vec![(0, "c".into_str()), (1, "a".into_str()), (2, "b".into_str())], strs);
+        // interning while iterating is allowed
+        for (_, s) in table.iter() {
+            table.intern(format!("{}{}", s, s));
+        }
+        assert_eq!(6, table.into_shared().iter().count());
+    }
+
+    #[test]
+    #[should_panic(expected = "not in this table")]
+    fn foreign_symbol() {
+        let other = SymbolTable::new();
+        let sym = other.intern("x");
+        SymbolTable::new().resolve(sym);
+    }
+
+    #[test]
+    fn concurrent() {
+        let table = Arc::new(SymbolTable::new());
+        let handles: Vec<_> = (0..8).map(|_| {
+            let table = table.clone();
+            thread::spawn(move || (0..100).map(|i| table.intern(format!("symbol #{}", i))).collect::<Vec<_>>())
+        }).collect();
+        let results: Vec<Vec<Symbol>> = handles.into_iter().map(|h| h.join().unwrap()).collect();
+        assert_eq!(100, table.len());
+        for syms in &results[1..] {
+            assert_eq!(&results[0], syms);
+        }
+        assert_eq!("symbol #42", table.resolve(results[0][42]));
+    }
+}
@@ -0,0 +1,196 @@
+//! Non-owning handles to strings
+//!
+//! `WeakStr` refers to a heap-backed `Str` without keeping it alive, as `std::sync::Weak` does for an `Arc`.
+//! Inline and static strings have nothing to free, so their handles hold the string itself and always upgrade.
+//! Handles compare and hash by identity, so a set of them can track which strings are still in use.
+
+use alloc::string::String;
+use alloc::sync::{Arc, Weak};
+use core::fmt::{self, Debug};
+use core::hash::{Hash, Hasher};
+
+use super::{StrN, SmallStr, SubStr};
+use heap::{ArcBytes, ArcStr, WeakArcStr};
+
+#[derive(Clone)]
+enum Inner<const N: usize> {
+    Small(SmallStr<N>),
+    Static(&'static str),
+    Rc(WeakArcStr),
+    Sub { rc: WeakArcStr, start: u32, len: u32 },
+    ArcString(Weak<String>),
+}
+
+/// A weak reference to a `StrN`, made by `StrN::downgrade()`
+///
+/// ```
+/// use strref::{IntoStr, WeakStr};
+///
+/// let s = "a string long enough to live on the heap".to_string().into_str();
+/// let weak: WeakStr = s.downgrade();
+/// assert_eq!(Some(s.clone()), weak.upgrade());
+/// drop(s);
+/// assert_eq!(None, weak.upgrade());
+/// ```
+#[derive(Clone)]
+pub struct WeakStr<const N: usize = 23> {
+    inner: Inner<N>,
+}
+
+impl<const N: usize> StrN<N> {
+    /// Makes a weak reference to this string, which doesn't keep a heap-backed string alive
+    pub fn downgrade(&self) -> WeakStr<N> {
+        let inner = match *self {
+            StrN::Small(t) => Inner::Small(t),
+            StrN::Static(s) => Inner::Static(s),
+            StrN::Rc(ref rc) => Inner::Rc(ArcStr::downgrade(rc)),
+            StrN::Sub(ref sub) => Inner::Sub {
+                rc: ArcBytes::downgrade(&sub.rc),
+                start: sub.start,
+                len: sub.len,
+            },
+            StrN::ArcString(ref rc) => Inner::ArcString(Arc::downgrade(rc)),
+        };
+        WeakStr { inner }
+    }
+}
+
+impl<const N: usize> WeakStr<N> {
+    /// Returns the string if it is still alive
+    ///
+    /// Inline and static strings are always alive.
+    pub fn upgrade(&self) -> Option<StrN<N>> {
+        match self.inner {
+            Inner::Small(t) => Some(StrN::Small(t)),
+            Inner::Static(s) => Some(StrN::Static(s)),
+            Inner::Rc(ref rc) => rc.upgrade().map(StrN::Rc),
+            Inner::Sub { ref rc, start, len } => rc.upgrade().map(|rc| StrN::Sub(SubStr { rc: rc.into_bytes(), start, len })),
+            Inner::ArcString(ref rc) => rc.upgrade().map(StrN::ArcString),
+        }
+    }
+
+    /// Returns the number of strings keeping the referenced one alive
+    ///
+    /// Inline and static strings never expire and always count as one.
+    pub fn strong_count(&self) -> usize {
+        match self.inner {
+            Inner::Small(_) | Inner::Static(_) => 1,
+            Inner::Rc(ref rc) | Inner::Sub { ref rc, .. } => rc.strong_count(),
+            Inner::ArcString(ref rc) => rc.strong_count(),
+        }
+    }
+
+    /// Returns true if both handles refer to the same string: the same range of the same allocation,
+    /// the same static string, or equal inline strings
+    ///
+    /// Like `Eq`, this never upgrades, so it also works for strings that have expired.
+    pub fn ptr_eq(this: &WeakStr<N>, other: &WeakStr<N>) -> bool {
+        match (&this.inner, &other.inner) {
+            (Inner::Small(a), Inner::Small(b)) => a == b,
+            (Inner::Static(a), Inner::Static(b)) => a.as_ptr() == b.as_ptr() && a.len() == b.len(),
+            (Inner::Rc(a), Inner::Rc(b)) => WeakArcStr::ptr_eq(a, b),
+            (Inner::Sub { rc: a, start: s1, len: l1 }, Inner::Sub { rc: b, start: s2, len: l2 }) =>
+                WeakArcStr::ptr_eq(a, b) && s1 == s2 && l1 == l2,
+            (Inner::ArcString(a), Inner::ArcString(b)) => Weak::ptr_eq(a, b),
+            _ => false,
+        }
+    }
+}
+
+impl<const N: usize> Debug for WeakStr<N> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.inner {
+            Inner::Small(ref t) => Debug::fmt(t, f),
+            Inner::Static(s) => Debug::fmt(s, f),
+            _ => f.write_str("(WeakStr)"),
+        }
+    }
+}
+
+impl<const N: usize> PartialEq for WeakStr<N> {
+    fn eq(&self, other: &WeakStr<N>) -> bool {
+        WeakStr::ptr_eq(self, other)
+    }
+}
+
+impl<const N: usize> Eq for WeakStr<N> {}
+
+impl<const N: usize> Hash for WeakStr<N> {
+    /// Hashes the identity that `ptr_eq()` compares, never the contents of a heap-backed string
+    fn hash<H: Hasher>(&self, h: &mut H) {
+        match self.inner {
+            Inner::Small(ref t) => t.hash(h),
+            Inner::Static(s) => (s.as_ptr(), s.len()).hash(h),
+            Inner::Rc(ref rc) => rc.as_ptr().hash(h),
+            Inner::Sub { ref rc, start, len } => (rc.as_ptr(), start, len).hash(h),
+            Inner::ArcString(ref rc) => rc.as_ptr().hash(h),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::WeakStr;
+    use super::super::{Str, IntoStr};
+    use std::collections::HashSet;
+    use std::sync::Arc;
+
+    const LONG: &str = "a string long enough to live on the heap";
+
+    #[test]
+    fn upgrade() {
+        let rc = LONG.to_string().into_str();
+        let sub = rc.slice(2..);
+        let arc = Arc::new(LONG.to_string()).into_str();
+        let weak: Vec<WeakStr> = [&rc, &sub, &arc].iter().map(|s| s.downgrade()).collect();
+        assert_eq!(Some(LONG), weak[0].upgrade().as_deref());
+        assert_eq!(Some(&LONG[2..]), weak[1].upgrade().as_deref());
+        assert_eq!(sub.as_ptr(), weak[1].upgrade().unwrap().as_ptr());
+        assert_eq!(2, weak[0].strong_count());
+        drop((rc, sub, arc));
+        for w in &weak {
+            assert_eq!(None, w.upgrade());
+            assert_eq!(0, w.strong_count());
+        }
+    }
+
+    #[test]
+    fn small_and_static_never_expire() {
+        let small = "short".to_string().into_str();
+        let weak = small.downgrade();
+        drop(small);
+        assert_eq!(Some("short".into_str()), weak.upgrade());
+        let weak = Str::from_static(LONG).downgrade();
+        assert_eq!(Some(LONG), weak.upgrade().as_deref());
+        assert_eq!(1, weak.strong_count());
+    }
+
+    #[test]
+    fn identity() {
+        let a = LONG.to_string().into_str();
+        let b = LONG.to_string().into_str();
+        assert_eq!(a, b);
+        assert_eq!(a.downgrade(), a.clone().downgrade());
+        assert_ne!(a.downgrade(), b.downgrade());
+        assert_ne!(a.slice(1..).downgrade(), a.slice(2..).downgrade());
+        assert_eq!("short".to_string().into_str().downgrade(), "short".to_string().into_str().downgrade());
+        // identity outlives the string
+        let (wa, wb) = (a.downgrade(), b.downgrade());
+        drop((a, b));
+        assert!(WeakStr::ptr_eq(&wa, &wa.clone()));
+        assert!(!WeakStr::ptr_eq(&wa, &wb));
+    }
+
+    #[test]
+    fn self_cleaning_set() {
+        let keep = LONG.to_string().into_str();
+        let mut live = HashSet::new();
+        for s in [keep.clone(), format!("{} too", LONG).into_str(), keep.clone()].iter() {
+            live.insert(s.downgrade());
+        }
+        assert_eq!(2, live.len());
+        live.retain(|w| w.upgrade().is_some());
+        assert_eq!(1, live.len());
+        assert!(live.contains(&keep.downgrade()));
+    }
+}